@@ -1,25 +1,39 @@
 pub mod input;
+pub mod input_map;
+pub mod window_state;
+pub mod console;
+pub mod hud;
+pub mod day_cycle;
 
 use std::borrow::BorrowMut;
 use std::sync::Mutex;
 use std::{time::SystemTime, sync::Arc};
 use winit::event::{WindowEvent, Event, KeyboardInput, VirtualKeyCode, ElementState, MouseButton, MouseScrollDelta, DeviceEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::CursorGrabMode;
+use cgmath::InnerSpace;
 
-use crate::gpu_utils::WgpuState;
+use crate::gpu_utils::{WgpuState, WgpuInitError};
 use crate::rendering::GameRenderer;
+use crate::rendering::debug_rendering::{DebugObject, DebugLine};
 use crate::voxel::brick_map::{BrickMap, SizedBrickMap};
 use crate::voxel::octree::Octree;
-use crate::voxel::{Voxel, VoxelData, VoxelStorage};
+use crate::voxel::{Voxel, VoxelData, VoxelStorage, IVoxel, coords};
 
-use crate::math::{Vec3, Color, Vec2};
+use crate::math::{Vec3, Color, Vec2, AABB};
 use crate::camera::{Camera, CameraEntity};
 use crate::voxel::terrain::{VoxelTerrain, TerrainInfo};
+use crate::voxel::world_gen::{NoiseArgs, SurfaceArgs};
 
 pub type WinitWindow = winit::window::Window;
 pub type WindowSize = winit::dpi::PhysicalSize<u32>;
 pub type WindowPosition = winit::dpi::PhysicalPosition<u32>;
 use self::input::*;
+use self::input_map::InputMap;
+use self::window_state::WindowState;
+use self::console::Console;
+use self::hud::{Hud, HudInfo};
+use self::day_cycle::DayCycle;
 
 type Storage = SizedBrickMap<Voxel, 4>;
 
@@ -28,12 +42,18 @@ struct AppState
     app_name: String,
     current_time: SystemTime,
     frame_builder: FrameStateBuilder,
+    input_map: InputMap,
 
     size: WindowSize,
     window_handle: Arc<WinitWindow>,
+    cursor_captured: bool,
     renderer: GameRenderer<Storage>,
 
     wgpu_state: WgpuState,
+    gilrs: Option<gilrs::Gilrs>,
+    console: Console<AppState>,
+    hud: Hud,
+    day_cycle: DayCycle,
 
     // TEMP
     camera_entity: CameraEntity,
@@ -43,28 +63,73 @@ struct AppState
 pub async fn run()
 {
     let name = "Voxel Game";
-    let (event_loop, window) = get_window();
-    let mut app_state = AppState::new(name, &event_loop, window).await;
+    let (event_loop, window) = get_window(WindowState::load(window_state::DEFAULT_SAVE_PATH));
+    let mut app_state = match AppState::new(name, &event_loop, window).await
+    {
+        Ok(app_state) => app_state,
+        Err(e) => 
+        {
+            eprintln!("Failed to start {}, the graphics card or its drivers may not be supported.\n{}", name, e);
+            return;
+        }
+    };
 
     event_loop.run(move |event, _, control_flow| {
         app_state.on_event(event, control_flow)
     })
 }
 
-fn get_window() -> (EventLoop<()>, WinitWindow)
+/// Hides and locks the cursor to the window for mouse look, or gives it back to the system
+fn set_cursor_grab(window: &WinitWindow, grabbed: bool)
+{
+    if grabbed
+    {
+        // not every platform supports both modes
+        let result = window.set_cursor_grab(CursorGrabMode::Confined)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked));
+
+        if let Err(e) = result
+        {
+            eprintln!("Failed to grab the cursor: {:?}", e);
+        }
+    }
+    else if let Err(e) = window.set_cursor_grab(CursorGrabMode::None)
+    {
+        eprintln!("Failed to release the cursor: {:?}", e);
+    }
+
+    window.set_cursor_visible(!grabbed);
+}
+
+fn get_window(saved_state: Option<WindowState>) -> (EventLoop<()>, WinitWindow)
 {
     let event_loop = EventLoop::new();
-    let window = WinitWindow::new(&event_loop).unwrap();
+    let mut builder = winit::window::WindowBuilder::new();
+
+    if let Some(state) = saved_state
+    {
+        builder = builder
+            .with_inner_size(state.size())
+            .with_maximized(state.maximized);
+
+        // the monitor it was on might have been disconnected since
+        if state.is_on_screen(&event_loop)
+        {
+            builder = builder.with_position(state.position());
+        }
+    }
+
+    let window = builder.build(&event_loop).unwrap();
     (event_loop, window)  
 }
 
 impl AppState
 {
-    async fn new<T>(name: &str, event_loop: &EventLoop<T>, window: WinitWindow) -> Self
+    async fn new<T>(name: &str, event_loop: &EventLoop<T>, window: WinitWindow) -> Result<Self, WgpuInitError>
         where T : 'static
     {
         window.set_title(name);
-        let wgpu_state = WgpuState::new(&window).await;
+        let wgpu_state = WgpuState::new(&window).await?;
         let window_handle = Arc::new(window);
         let size = window_handle.inner_size();
 
@@ -78,33 +143,61 @@ impl AppState
             up: Vec3::unit_y(),
             aspect,
             fov: 45.0,
-            near: 0.1,
-            far: 100000.0
+            near: Camera::DEFAULT_NEAR,
+            far: Camera::DEFAULT_FAR
         };
 
         let terrain = generate_terrain(wgpu_state.device().clone(), wgpu_state.queue().clone());
 
-        let renderer = GameRenderer::new(terrain.clone(), camera.clone(), wgpu_state.device().clone(), wgpu_state.surface().clone(), wgpu_state.queue().clone(), &wgpu_state.surface_config(), event_loop, window_handle.clone());
+        let renderer = GameRenderer::new(terrain.clone(), camera.clone(), wgpu_state.device().clone(), wgpu_state.surface().expect("Windowed state always has a surface").clone(), wgpu_state.queue().clone(), &wgpu_state.surface_config(), event_loop, window_handle.clone(), wgpu_state.supported_sample_counts().to_vec());
         let frame_builder = FrameStateBuilder::new(window_handle.clone(), FrameState::new(&window_handle));
 
-        Self
+        // gamepads are optional, so keep running with keyboard and mouse only if gilrs can't start
+        let gilrs = match gilrs::Gilrs::new()
+        {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => 
+            {
+                eprintln!("Failed to initialize gamepad support: {:?}", e);
+                None
+            }
+        };
+
+        Ok(Self
         {
             app_name: name.into(),
             current_time: SystemTime::now(),
             frame_builder,
+            input_map: InputMap::load(input_map::DEFAULT_SAVE_PATH),
             size,
             window_handle,
+            cursor_captured: false,
             wgpu_state,
+            gilrs,
+            console: create_console(),
+            hud: Hud::new(),
+            day_cycle: DayCycle::new(day_cycle::DEFAULT_DAY_LENGTH),
             renderer,
             camera_entity: CameraEntity::new(camera, 20.0, 50.0, 80.0),
             terrain,
-        }
+        })
     }
 
     fn on_event<'a, T>(&mut self, event: Event<'a, T>, control_flow: &mut ControlFlow)
     {
+        // handled before the gui, which would otherwise type the backtick into the console
+        if let Event::WindowEvent { event: ref window_event, window_id } = event
+        {
+            if window_id == self.window_handle.id() && self.handle_console_toggle(window_event)
+            {
+                return;
+            }
+        }
+
         if self.renderer.handle_event(&event)
         {
+            // the gui wants the mouse, so give it back
+            self.set_cursor_captured(false);
             return;
         }
 
@@ -120,6 +213,33 @@ impl AppState
             {
                 match event 
                 {
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } if self.cursor_captured => self.set_cursor_captured(false),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Tab),
+                                ..
+                            },
+                        ..
+                    } => self.set_cursor_captured(!self.cursor_captured),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F2),
+                                ..
+                            },
+                        ..
+                    } => self.capture_screenshot(),
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
                         input:
@@ -130,6 +250,7 @@ impl AppState
                             },
                         ..
                     } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Focused(false) => self.set_cursor_captured(false),
                     WindowEvent::Resized(physical_size) => {
                         self.resize(*physical_size);
                     }
@@ -157,11 +278,162 @@ impl AppState
 
             Event::LoopDestroyed => {
                 self.renderer.on_close();
+                self.input_map.save(input_map::DEFAULT_SAVE_PATH);
+                if let Some(window_state) = WindowState::from_window(&self.window_handle)
+                {
+                    window_state.save(window_state::DEFAULT_SAVE_PATH);
+                }
             }
             _ => {}
         }
     }
 
+    fn set_cursor_captured(&mut self, captured: bool)
+    {
+        if self.cursor_captured == captured
+        {
+            return;
+        }
+
+        self.cursor_captured = captured;
+        set_cursor_grab(&self.window_handle, captured);
+        self.camera_entity.set_mouse_look(captured);
+
+        // drop whatever the mouse did while grabbing, so the view doesn't jump
+        self.frame_builder.clear_mouse_delta();
+    }
+
+    /// Returns true if the event was the console toggle key, or the character it types
+    fn handle_console_toggle(&mut self, event: &WindowEvent) -> bool
+    {
+        match event
+        {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Grave),
+                        ..
+                    },
+                ..
+            } =>
+            {
+                self.console.toggle();
+                if self.console.is_open()
+                {
+                    self.set_cursor_captured(false);
+                }
+
+                true
+            },
+            WindowEvent::KeyboardInput { input: KeyboardInput { virtual_keycode: Some(VirtualKeyCode::Grave), .. }, .. } 
+            | WindowEvent::ReceivedCharacter('`') => true,
+            _ => false
+        }
+    }
+
+    fn execute_console_command(&mut self, line: &str)
+    {
+        // the commands need all of the app state, which includes the console
+        let mut console = std::mem::replace(&mut self.console, Console::new(0));
+        console.execute(self, line);
+        self.console = console;
+    }
+
+    fn hud_info(&self) -> HudInfo
+    {
+        let camera = self.camera_entity.camera();
+        let look_direction = (camera.target - camera.eye).normalize();
+        let terrain = self.terrain.lock().unwrap();
+
+        let (chunk_length, voxel_size) = (terrain.info().chunk_length(), terrain.info().voxel_size);
+        let (chunk_index, _) = coords::world_to_chunk(camera.eye, chunk_length, voxel_size);
+
+        let face_count = terrain.chunks()
+            .filter_map(|c| c.render_data())
+            .map(|r| r.face_instance_buffer().length())
+            .sum();
+
+        let picked = if self.hud.pick_voxel
+        {
+            terrain.raycast(camera.eye, look_direction, Hud::PICK_DISTANCE)
+                .map(|hit| (hit, terrain.voxel_types()[hit.voxel.id() as usize].name()))
+        }
+        else 
+        {
+            None
+        };
+
+        let chunk_labels = if self.hud.chunk_labels
+        {
+            terrain.chunks()
+                .map(|c| (coords::chunk_to_world_origin(c.index(), chunk_length, voxel_size), c.index()))
+                .collect()
+        }
+        else 
+        {
+            vec![]
+        };
+
+        HudInfo 
+        { 
+            camera: camera.clone(),
+            position: camera.eye, 
+            look_direction, 
+            camera_speed: self.camera_entity.speed(),
+            sprinting: self.camera_entity.is_sprinting(),
+            flying: self.camera_entity.is_flying(),
+            chunk_index, 
+            chunk_count: terrain.chunks().count(), 
+            face_count, 
+            picked,
+            terrain_gpu_ms: self.renderer.profiling_info().draw_time_ms,
+            chunk_labels
+        }
+    }
+
+    /// Chunk bounds, and an outline of the picked voxel with the normal of the face it was hit on
+    fn debug_objects(&self) -> Vec<DebugObject>
+    {
+        let mut objects = vec![];
+        let camera = self.camera_entity.camera();
+        let terrain = self.terrain.lock().unwrap();
+        let voxel_size = terrain.info().voxel_size;
+
+        if self.hud.chunk_bounds
+        {
+            for chunk in terrain.chunks()
+            {
+                let aabb = coords::chunk_bounds(chunk.index(), terrain.info().chunk_length(), voxel_size);
+                objects.push(DebugObject::from_aabb(&aabb, Color::GREEN));
+            }
+        }
+
+        let hit = if self.hud.pick_voxel { terrain.raycast(camera.eye, (camera.target - camera.eye).normalize(), Hud::PICK_DISTANCE) } else { None };
+        if let Some(hit) = hit
+        {
+            // grown a little so the outline isn't hidden inside the voxel's faces
+            let margin = voxel_size * 0.02;
+            let min = coords::voxel_to_world(hit.voxel_index, voxel_size) - Vec3::new(margin, margin, margin);
+            let outline = AABB::new(min, min + Vec3::new(1.0, 1.0, 1.0) * (voxel_size + margin * 2.0));
+            objects.push(DebugObject::from_aabb(&outline, Color::WHITE));
+
+            let center = (hit.voxel_index.cast::<f32>().unwrap() + Vec3::new(0.5, 0.5, 0.5)) * voxel_size;
+            let normal = hit.normal.cast::<f32>().unwrap();
+            let face_center = center + normal * voxel_size * 0.5;
+            objects.push(DebugObject::Line(DebugLine::new(face_center, face_center + normal * voxel_size * 2.0, Color::RED)));
+        }
+
+        objects
+    }
+
+    fn capture_screenshot(&mut self)
+    {
+        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        let path = format!("screenshot_{}.png", timestamp);
+        self.renderer.capture_frame(std::path::Path::new(&path));
+    }
+
     fn resize(&mut self, new_size: WindowSize)
     {
         if new_size.width > 0 && new_size.height > 0
@@ -176,17 +448,61 @@ impl AppState
 
     fn on_render(&mut self) -> Result<(), wgpu::SurfaceError>
     {        
-        self.renderer.render()?;
+        let mut present_mode = self.wgpu_state.present_mode();
+        let hud_info = self.hud_info();
+        let hud = &mut self.hud;
+        let day_cycle = &mut self.day_cycle;
+        let console = &mut self.console;
+        let mut console_line = None;
+        self.renderer.render(&mut present_mode, self.wgpu_state.supported_present_modes(), |ctx| 
+        {
+            hud.ui(ctx, &hud_info);
+            day_cycle.ui(ctx);
+            console_line = console.ui(ctx);
+        })?;
+
+        if let Some(line) = console_line
+        {
+            self.execute_console_command(&line);
+        }
+
+        self.renderer.set_profiling(self.hud.gpu_timing);
+
+        if present_mode != self.wgpu_state.present_mode()
+        {
+            self.wgpu_state.set_present_mode(present_mode);
+        }
+
         Ok(())
     }
 
     fn on_update(&mut self)
     {
         let delta_time = self.current_time.elapsed().unwrap().as_secs_f32();
+        if let Some(gilrs) = &mut self.gilrs
+        {
+            self.frame_builder.on_gamepad_update(gilrs);
+        }
+
         let frame_state = self.frame_builder.build(delta_time);
 
-        self.camera_entity.update(&frame_state);
-        self.renderer.update(self.camera_entity.camera(), &vec![], delta_time);
+        // the keys are going into the console instead, but console commands can still move the camera
+        if !self.console.is_open()
+        {
+            self.camera_entity.update(&frame_state, &self.input_map);
+        }
+        else 
+        {
+            self.camera_entity.update_view(delta_time);
+        }
+
+        self.day_cycle.update(delta_time);
+        if self.day_cycle.enabled
+        {
+            self.renderer.set_sky(self.day_cycle.sky_color(), self.day_cycle.light());
+        }
+        let debug_objects = self.debug_objects();
+        self.renderer.update(self.camera_entity.camera(), &debug_objects, delta_time);
         self.current_time = SystemTime::now();
         self.terrain.lock().unwrap().tick();
 
@@ -194,17 +510,77 @@ impl AppState
     }
 }
 
+fn create_console() -> Console<AppState>
+{
+    let mut console = Console::new(console::DEFAULT_SCROLLBACK);
+
+    console.register("tp", |app, args| 
+    {
+        let [x, y, z] = args else { return Err("Usage: tp <x> <y> <z>".into()); };
+        let parse = |arg: &str| arg.parse::<f32>().map_err(|_| format!("'{}' is not a number", arg));
+        let position = Vec3::new(parse(*x)?, parse(*y)?, parse(*z)?);
+
+        // keep looking the same way
+        let camera = app.camera_entity.mut_camera();
+        let look = camera.target - camera.eye;
+        camera.eye = (position.x, position.y, position.z).into();
+        camera.target = camera.eye + look;
+
+        Ok(format!("Teleported to ({}, {}, {})", position.x, position.y, position.z))
+    });
+
+    console.register("smoothing", |app, args| 
+    {
+        let [seconds] = args else { return Ok(format!("Camera smoothing is {}s", app.camera_entity.smoothing())); };
+        let seconds = seconds.parse::<f32>().map_err(|_| format!("'{}' is not a number", seconds))?;
+        app.camera_entity.set_smoothing(seconds);
+        Ok(format!("Set camera smoothing to {}s", app.camera_entity.smoothing()))
+    });
+
+    console.register("clip", |app, args| 
+    {
+        let [near, far] = args else 
+        { 
+            let camera = app.camera_entity.camera();
+            return Ok(format!("Clip planes are near {} and far {}", camera.near, camera.far)); 
+        };
+
+        let parse = |arg: &str| arg.parse::<f32>().map_err(|_| format!("'{}' is not a number", arg));
+        let (near, far) = (parse(*near)?, parse(*far)?);
+        // written so NaN fails it too, "nan" and "inf" parse as floats
+        if !(near.is_finite() && far.is_finite() && near > 0.0 && far > near)
+        {
+            return Err("Clip planes must be finite and satisfy 0 < near < far".into());
+        }
+
+        app.camera_entity.mut_camera().set_clip_planes(near, far);
+        Ok(format!("Set clip planes to near {} and far {}", near, far))
+    });
+
+    console.register("reload", |app, _| 
+    {
+        app.input_map = InputMap::load(input_map::DEFAULT_SAVE_PATH);
+        Ok(format!("Reloaded the input map from {}", input_map::DEFAULT_SAVE_PATH))
+    });
+
+    console
+}
+
 fn generate_terrain<TStorage>(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Arc<Mutex<VoxelTerrain<TStorage>>> 
     where TStorage : VoxelStorage<Voxel> + Send + 'static
 {        
-    let sand_color = Color::new(0.76, 0.698, 0.502, 1.0);
+    let sand_color = Color::from_srgb_u8(194, 178, 128, 255);
+    let dirt_color = Color::from_srgb_u8(112, 79, 51, 255);
+    let stone_color = Color::from_srgb_u8(128, 128, 128, 255);
 
     let voxel_types = vec!
     [
-        VoxelData::new(Color::WHITE), 
-        VoxelData::new(Color::BLUE),
-        VoxelData::new(sand_color),
-        VoxelData::new(Color::GREEN)
+        VoxelData::new("Default", Color::WHITE), 
+        VoxelData::new("Water", Color::BLUE),
+        VoxelData::new("Sand", sand_color),
+        VoxelData::new("Grass", Color::GREEN),
+        VoxelData::new("Dirt", dirt_color),
+        VoxelData::new("Stone", stone_color)
     ];
         
     const CHUNK_DEPTH: usize = 8;
@@ -215,6 +591,9 @@ fn generate_terrain<TStorage>(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>
         chunk_depth: CHUNK_DEPTH,
         voxel_size: VOXEL_SIZE,
         voxel_types: Arc::new(voxel_types),
+        noise_args: NoiseArgs::default(),
+        surface_args: SurfaceArgs::default(),
+        gpu_meshing: false
     };
 
     let terrain = Arc::new(Mutex::new(VoxelTerrain::new(info, device.clone(), queue))); 
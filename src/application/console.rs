@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, VecDeque};
+
+pub const DEFAULT_SCROLLBACK: usize = 200;
+
+/// Gets the arguments after the command name, and returns the line to print or an error
+pub type ConsoleCommand<T> = fn(&mut T, &[&str]) -> Result<String, String>;
+
+/// A drop down command line, the commands get whatever `T` owns the game state
+pub struct Console<T>
+{
+    commands: BTreeMap<String, ConsoleCommand<T>>,
+    scrollback: VecDeque<String>,
+    scrollback_capacity: usize,
+    history: Vec<String>,
+    /// Where up/down is in `history`, `None` when editing a new line
+    history_index: Option<usize>,
+    input: String,
+    is_open: bool,
+    /// Set when the console opens or a line is submitted, so the input only grabs focus on those frames
+    wants_focus: bool
+}
+
+impl<T> Console<T>
+{
+    pub fn new(scrollback_capacity: usize) -> Self
+    {
+        Self
+        {
+            commands: BTreeMap::new(),
+            scrollback: VecDeque::with_capacity(scrollback_capacity),
+            scrollback_capacity,
+            history: Vec::new(),
+            history_index: None,
+            input: String::new(),
+            is_open: false,
+            wants_focus: false
+        }
+    }
+
+    pub fn is_open(&self) -> bool { self.is_open }
+    pub fn toggle(&mut self) 
+    { 
+        self.is_open = !self.is_open; 
+        self.wants_focus = self.is_open;
+    }
+
+    /// Replaces any command already registered under `name`, `help` and `clear` are built in and always win
+    pub fn register(&mut self, name: &str, command: ConsoleCommand<T>)
+    {
+        self.commands.insert(name.into(), command);
+    }
+
+    pub fn print(&mut self, line: impl Into<String>)
+    {
+        if self.scrollback.len() == self.scrollback_capacity
+        {
+            self.scrollback.pop_front();
+        }
+
+        self.scrollback.push_back(line.into());
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.scrollback.clear();
+    }
+
+    /// Splits `line` on whitespace and runs the command named by the first word against `target`
+    pub fn execute(&mut self, target: &mut T, line: &str)
+    {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let Some((name, args)) = words.split_first() else { return; };
+
+        self.print(format!("> {}", line));
+        self.history.push(line.into());
+
+        // these act on the console itself, so they can't be registered commands
+        let result = match (*name, self.commands.get(*name).copied())
+        {
+            ("help", _) => Ok(format!("Commands: help, clear, {}", self.commands.keys().cloned().collect::<Vec<_>>().join(", "))),
+            ("clear", _) => 
+            {
+                self.clear();
+                Ok(String::new())
+            },
+            (_, Some(command)) => command(target, args),
+            (_, None) => Err(format!("Unknown command '{}', try 'help'", name))
+        };
+
+        match result
+        {
+            Ok(output) if output.is_empty() => {},
+            Ok(output) => self.print(output),
+            Err(error) => self.print(format!("Error: {}", error))
+        }
+    }
+
+    /// Draws the console along the top of the screen if it is open, returns a line once it has been submitted
+    pub fn ui(&mut self, context: &egui::Context) -> Option<String>
+    {
+        if !self.is_open
+        {
+            return None;
+        }
+
+        let mut submitted = None;
+        egui::TopBottomPanel::top("Console")
+            .resizable(true)
+            .default_height(200.0)
+            .show(context, |ui|
+            {
+                let input_height = ui.spacing().interact_size.y + ui.spacing().item_spacing.y;
+                egui::ScrollArea::vertical()
+                    .max_height((ui.available_height() - input_height).max(0.0))
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui|
+                    {
+                        for line in &self.scrollback
+                        {
+                            ui.monospace(line);
+                        }
+                    });
+
+                let response = ui.add(egui::TextEdit::singleline(&mut self.input)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(f32::INFINITY));
+
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                {
+                    submitted = Some(std::mem::take(&mut self.input));
+                    self.history_index = None;
+
+                    // keep typing after submitting, instead of having to click back in
+                    self.wants_focus = true;
+                }
+                else if response.has_focus()
+                {
+                    if ui.input(|i| i.key_pressed(egui::Key::ArrowUp))
+                    {
+                        self.step_history(true);
+                    }
+                    else if ui.input(|i| i.key_pressed(egui::Key::ArrowDown))
+                    {
+                        self.step_history(false);
+                    }
+                }
+
+                if self.wants_focus
+                {
+                    response.request_focus();
+                    self.wants_focus = false;
+                }
+            });
+
+        submitted
+    }
+
+    fn step_history(&mut self, older: bool)
+    {
+        if self.history.is_empty()
+        {
+            return;
+        }
+
+        self.history_index = match (self.history_index, older)
+        {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (Some(_), false) => None
+        };
+
+        self.input = match self.history_index
+        {
+            Some(index) => self.history[index].clone(),
+            None => String::new()
+        };
+    }
+}
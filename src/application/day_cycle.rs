@@ -0,0 +1,106 @@
+use cgmath::InnerSpace;
+
+use crate::math::{Vec3, Color};
+use crate::voxel::voxel_rendering::LightInfo;
+
+pub const DEFAULT_DAY_LENGTH: f32 = 300.0;
+
+const DAY_SKY: Color = Color::new(0.1, 0.2, 0.3, 1.0);
+const DUSK_SKY: Color = Color::new(0.4, 0.18, 0.08, 1.0);
+const NIGHT_SKY: Color = Color::new(0.004, 0.005, 0.015, 1.0);
+
+const DAY_AMBIENT: f32 = 0.4;
+const NIGHT_AMBIENT: f32 = 0.04;
+
+/// Moves the sun around over `day_length` seconds, rising in +x and setting in -x
+#[derive(Debug, Clone)]
+pub struct DayCycle
+{
+    /// How far through the day it is, 0 is midnight and 0.5 is noon
+    pub time: f32,
+    /// In seconds
+    pub day_length: f32,
+    pub paused: bool,
+    /// When off, the sky and light are left alone, so they can be set by hand
+    pub enabled: bool
+}
+
+impl DayCycle
+{
+    pub fn new(day_length: f32) -> Self
+    {
+        Self
+        {
+            time: 0.5,
+            day_length,
+            paused: false,
+            enabled: true
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32)
+    {
+        if !self.paused
+        {
+            self.time = (self.time + delta_time / self.day_length).fract();
+        }
+    }
+
+    /// Normalized, points towards the sun
+    pub fn sun_direction(&self) -> Vec3<f32>
+    {
+        let angle = (self.time - 0.25) * std::f32::consts::TAU;
+
+        // tilted a little, so faces along the sun's path aren't lit the same
+        Vec3::new(angle.cos(), angle.sin(), 0.3).normalize()
+    }
+
+    /// How far the sun is above the horizon, from -1 to 1
+    pub fn sun_height(&self) -> f32
+    {
+        self.sun_direction().y
+    }
+
+    pub fn sky_color(&self) -> Color
+    {
+        let height = self.sun_height();
+        if height >= 0.0
+        {
+            DUSK_SKY.lerp(DAY_SKY, smoothstep(0.0, 0.3, height))
+        }
+        else 
+        {
+            DUSK_SKY.lerp(NIGHT_SKY, smoothstep(0.0, 0.2, -height))
+        }
+    }
+
+    pub fn light(&self) -> LightInfo
+    {
+        let height = self.sun_height();
+
+        // fades the sun out as it sets, instead of lighting faces from under the ground
+        let sun_strength = smoothstep(-0.05, 0.1, height);
+        let ambient = NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * smoothstep(-0.2, 0.2, height);
+        LightInfo::new(self.sun_direction() * sun_strength, ambient)
+    }
+
+    pub fn ui(&mut self, context: &egui::Context)
+    {
+        egui::Window::new("Day Cycle")
+            .resizable(false)
+            .default_open(false)
+            .show(context, |ui| 
+            {
+                ui.checkbox(&mut self.enabled, "Enabled");
+                ui.add(egui::Slider::new(&mut self.day_length, 10.0..=1200.0).text("Day length (s)"));
+                ui.checkbox(&mut self.paused, "Paused");
+                ui.add(egui::Slider::new(&mut self.time, 0.0..=1.0).text("Time of day"));
+            });
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32
+{
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
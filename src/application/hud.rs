@@ -0,0 +1,125 @@
+use crate::camera::Camera;
+use crate::math::{Vec2, Vec3, Point3D};
+use crate::voxel::terrain::TerrainHit;
+
+/// What the hud shows for the current frame
+#[derive(Debug, Clone)]
+pub struct HudInfo
+{
+    pub camera: Camera,
+    pub position: Point3D<f32>,
+    pub look_direction: Vec3<f32>,
+    pub camera_speed: f32,
+    pub sprinting: bool,
+    pub flying: bool,
+    pub chunk_index: Vec3<isize>,
+    pub chunk_count: usize,
+    pub face_count: u64,
+    /// `None` when nothing was hit, or when picking is turned off
+    pub picked: Option<(TerrainHit, &'static str)>,
+    /// `None` when gpu timing is turned off or not supported
+    pub terrain_gpu_ms: Option<f32>,
+    /// The world space origin of each chunk and its index, empty when the labels are turned off
+    pub chunk_labels: Vec<(Point3D<f32>, Vec3<isize>)>
+}
+
+/// A compact readout in the top left corner
+#[derive(Debug, Clone)]
+pub struct Hud
+{
+    /// Picking raycasts through the terrain every frame, so it can be turned off
+    pub pick_voxel: bool,
+    /// Reading back gpu timings stalls every frame, so it can be turned off
+    pub gpu_timing: bool,
+    pub chunk_labels: bool,
+    pub chunk_bounds: bool
+}
+
+impl Hud
+{
+    pub const PICK_DISTANCE: f32 = 64.0;
+
+    pub fn new() -> Self
+    {
+        Self
+        {
+            pick_voxel: false,
+            gpu_timing: false,
+            chunk_labels: false,
+            chunk_bounds: false
+        }
+    }
+
+    pub fn ui(&mut self, context: &egui::Context, info: &HudInfo)
+    {
+        egui::Area::new("Hud")
+            .anchor(egui::Align2::LEFT_TOP, egui::Vec2::new(8.0, 8.0))
+            .show(context, |ui|
+            {
+                egui::Frame::popup(ui.style()).show(ui, |ui|
+                {
+                    let p = info.position;
+                    let d = info.look_direction;
+                    let c = info.chunk_index;
+                    ui.monospace(format!("Position: ({:.2}, {:.2}, {:.2})", p.x, p.y, p.z));
+                    ui.monospace(format!("Looking:  ({:.2}, {:.2}, {:.2})", d.x, d.y, d.z));
+                    ui.monospace(format!("Chunk:    ({}, {}, {})", c.x, c.y, c.z));
+                    ui.monospace(format!("Speed:    {:.1}{}{}", info.camera_speed, if info.flying { " flying" } else { " walking" }, if info.sprinting { " (sprinting)" } else { "" }));
+
+                    // every face is an instance of one quad
+                    ui.monospace(format!("Chunks: {}  Faces: {}  Triangles: {}", info.chunk_count, info.face_count, info.face_count * 2));
+
+                    ui.checkbox(&mut self.pick_voxel, "Voxel under crosshair");
+                    if self.pick_voxel
+                    {
+                        match &info.picked
+                        {
+                            Some((hit, name)) =>
+                            {
+                                let v = hit.voxel_index;
+                                ui.monospace(format!("{} at ({}, {}, {}), {:.2} away", name, v.x, v.y, v.z, hit.distance));
+                            },
+                            None => { ui.monospace("Nothing"); }
+                        }
+                    }
+
+                    ui.checkbox(&mut self.chunk_labels, "Chunk labels");
+                    ui.checkbox(&mut self.chunk_bounds, "Chunk bounds");
+                    ui.checkbox(&mut self.gpu_timing, "Gpu timing");
+                    if self.gpu_timing
+                    {
+                        match info.terrain_gpu_ms
+                        {
+                            Some(ms) => { ui.monospace(format!("Terrain: {:.3}ms", ms)); },
+                            None => { ui.monospace("Terrain: n/a"); }
+                        }
+                    }
+                });
+            });
+
+        if self.chunk_labels
+        {
+            Self::draw_chunk_labels(context, info);
+        }
+    }
+
+    fn draw_chunk_labels(context: &egui::Context, info: &HudInfo)
+    {
+        // in points, so the positions line up with the gui
+        let screen = context.screen_rect();
+        let viewport = Vec2::new(screen.width(), screen.height());
+        let painter = context.layer_painter(egui::LayerId::background());
+
+        for (origin, index) in &info.chunk_labels
+        {
+            let Some(position) = info.camera.world_to_screen(*origin, viewport) else { continue; };
+            painter.text(
+                egui::pos2(position.x, position.y), 
+                egui::Align2::CENTER_CENTER, 
+                format!("({}, {}, {})", index.x, index.y, index.z), 
+                egui::FontId::monospace(12.0), 
+                egui::Color32::WHITE
+            );
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use std::sync::Arc;
 
 use cgmath::Zero;
+use gilrs::{Gilrs, Axis, Button};
 use winit::event::{VirtualKeyCode, MouseButton, MouseScrollDelta, Event, KeyboardInput, ElementState, DeviceEvent};
 use super::{WindowEvent, WindowSize, WinitWindow};
 
 use crate::math::Vec2;
+use super::input_map::InputMap;
 
 
 
@@ -23,6 +25,10 @@ pub struct FrameState
     mouse_buttons_down: Vec<MouseButton>,
     mouse_scroll_delta: Option<MouseScrollDelta>,
 
+    gamepad_connected: bool,
+    gamepad_axes: Vec<(Axis, f32)>,
+    gamepad_buttons_down: Vec<Button>,
+
     window_size: WindowSize,
     delta_time: f32
 }
@@ -37,11 +43,38 @@ impl FrameState
     pub fn is_mouse_button_pressed(&self, mouse_button: MouseButton) -> bool { self.mouse_buttons_pressed.contains(&mouse_button) }
     pub fn is_mouse_button_released(&self, mouse_button: MouseButton) -> bool { self.mouse_buttons_released.contains(&mouse_button) }
 
+    pub fn is_action_down(&self, map: &InputMap, action: &str) -> bool { map.bindings(action).iter().any(|b| b.is_down(self)) }
+    pub fn is_action_pressed(&self, map: &InputMap, action: &str) -> bool { map.bindings(action).iter().any(|b| b.is_pressed(self)) }
+    pub fn is_action_released(&self, map: &InputMap, action: &str) -> bool { map.bindings(action).iter().any(|b| b.is_released(self)) }
+
+    pub fn is_gamepad_connected(&self) -> bool { self.gamepad_connected }
+    pub fn is_gamepad_button_down(&self, button: Button) -> bool { self.gamepad_buttons_down.contains(&button) }
+    
+    /// In the range -1 to 1, or 0 when no gamepad is connected
+    pub fn gamepad_axis(&self, axis: Axis) -> f32 
+    { 
+        self.gamepad_axes.iter().find(|(a, _)| *a == axis).map_or(0.0, |(_, v)| *v) 
+    }
+
     pub fn delta_time(&self) -> f32 { self.delta_time }
 
     pub fn mouse_position(&self) -> Vec2<f32> { self.mouse_position }
     pub fn mouse_delta(&self) -> Vec2<f32> { self.mouse_delta }
 
+    /// Vertical scroll this frame in lines, positive when scrolling up/away from the user
+    pub fn scroll_delta(&self) -> f32
+    {
+        // rough height of a line for touchpads that report in pixels
+        const PIXELS_PER_LINE: f32 = 20.0;
+
+        match self.mouse_scroll_delta
+        {
+            Some(MouseScrollDelta::LineDelta(_, y)) => y,
+            Some(MouseScrollDelta::PixelDelta(position)) => position.y as f32 / PIXELS_PER_LINE,
+            None => 0.0
+        }
+    }
+
     pub fn new(window: &WinitWindow) -> Self
     {
         Self 
@@ -54,6 +87,9 @@ impl FrameState
             mouse_buttons_released: vec![], 
             mouse_buttons_down: vec![], 
             mouse_scroll_delta: None, 
+            gamepad_connected: false,
+            gamepad_axes: vec![],
+            gamepad_buttons_down: vec![],
             window_size: window.inner_size(),
             delta_time: 0.0,
             mouse_position: Vec2::new(0.0, 0.0)
@@ -74,6 +110,10 @@ pub struct FrameStateBuilder
     mouse_buttons_down: Vec<MouseButton>,
     mouse_scroll_delta: Option<MouseScrollDelta>,
 
+    gamepad_connected: bool,
+    gamepad_axes: Vec<(Axis, f32)>,
+    gamepad_buttons_down: Vec<Button>,
+
     window_size: WindowSize,
     current_mouse_position: Vec2<f32>,
     mouse_delta: Vec2<f32>
@@ -97,6 +137,9 @@ impl FrameStateBuilder
             mouse_buttons_released: vec![], 
             mouse_buttons_down, 
             mouse_scroll_delta: None, 
+            gamepad_connected: false,
+            gamepad_axes: vec![],
+            gamepad_buttons_down: vec![],
             window_size,
             current_mouse_position: previous_frame.mouse_position,
             mouse_delta: Vec2::zero()
@@ -208,6 +251,32 @@ impl FrameStateBuilder
         }
     }
 
+    /// Drains the pending gamepad events and snapshots the first connected gamepad
+    pub fn on_gamepad_update(&mut self, gilrs: &mut Gilrs)
+    {
+        while gilrs.next_event().is_some() {}
+
+        let Some((_, gamepad)) = gilrs.gamepads().find(|(_, g)| g.is_connected()) else { return; };
+
+        const AXES: [Axis; 6] = [Axis::LeftStickX, Axis::LeftStickY, Axis::LeftZ, Axis::RightStickX, Axis::RightStickY, Axis::RightZ];
+        const BUTTONS: [Button; 19] = 
+        [
+            Button::South, Button::East, Button::North, Button::West, Button::C, Button::Z,
+            Button::LeftTrigger, Button::LeftTrigger2, Button::RightTrigger, Button::RightTrigger2,
+            Button::Select, Button::Start, Button::Mode, Button::LeftThumb, Button::RightThumb,
+            Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight
+        ];
+
+        self.gamepad_connected = true;
+        self.gamepad_axes = AXES.iter().map(|a| (*a, gamepad.value(*a))).collect();
+        self.gamepad_buttons_down = BUTTONS.iter().copied().filter(|b| gamepad.is_pressed(*b)).collect();
+    }
+
+    pub fn clear_mouse_delta(&mut self)
+    {
+        self.mouse_delta = Vec2::zero();
+    }
+
     pub fn build(&self, delta_time: f32) -> FrameState
     {
         FrameState 
@@ -221,6 +290,9 @@ impl FrameStateBuilder
             mouse_buttons_released: self.mouse_buttons_released.clone(), 
             mouse_buttons_down: self.mouse_buttons_down.clone(), 
             mouse_scroll_delta: self.mouse_scroll_delta, 
+            gamepad_connected: self.gamepad_connected,
+            gamepad_axes: self.gamepad_axes.clone(),
+            gamepad_buttons_down: self.gamepad_buttons_down.clone(),
             window_size: self.window_size,
             delta_time
         }
@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, fs::File, io::{Write, Read}};
+
+use serde::{Serialize, Deserialize};
+use winit::event::{VirtualKeyCode, MouseButton};
+
+use super::input::FrameState;
+
+pub const DEFAULT_SAVE_PATH: &str = "input_map.yaml";
+
+pub mod actions
+{
+    pub const MOVE_FORWARD: &str = "move_forward";
+    pub const MOVE_BACKWARD: &str = "move_backward";
+    pub const MOVE_LEFT: &str = "move_left";
+    pub const MOVE_RIGHT: &str = "move_right";
+    pub const MOVE_UP: &str = "move_up";
+    pub const MOVE_DOWN: &str = "move_down";
+    pub const SPRINT: &str = "sprint";
+    pub const SPEED_UP: &str = "speed_up";
+    pub const SPEED_DOWN: &str = "speed_down";
+    pub const TOGGLE_CAMERA_MODE: &str = "toggle_camera_mode";
+    pub const TOGGLE_FLY: &str = "toggle_fly";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputBinding
+{
+    Key(VirtualKeyCode),
+    Mouse(MouseButton)
+}
+
+impl InputBinding
+{
+    pub fn is_down(&self, frame_state: &FrameState) -> bool
+    {
+        match self
+        {
+            InputBinding::Key(key) => frame_state.is_key_down(*key),
+            InputBinding::Mouse(button) => frame_state.is_mouse_button_down(*button),
+        }
+    }
+
+    pub fn is_pressed(&self, frame_state: &FrameState) -> bool
+    {
+        match self
+        {
+            InputBinding::Key(key) => frame_state.is_key_pressed(*key),
+            InputBinding::Mouse(button) => frame_state.is_mouse_button_pressed(*button),
+        }
+    }
+
+    pub fn is_released(&self, frame_state: &FrameState) -> bool
+    {
+        match self
+        {
+            InputBinding::Key(key) => frame_state.is_key_released(*key),
+            InputBinding::Mouse(button) => frame_state.is_mouse_button_released(*button),
+        }
+    }
+}
+
+/// Maps named actions to the keys and mouse buttons that trigger them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputMap
+{
+    bindings: BTreeMap<String, Vec<InputBinding>>
+}
+
+impl InputMap
+{
+    pub fn new() -> Self
+    {
+        Self 
+        { 
+            bindings: BTreeMap::new() 
+        }
+    }
+
+    pub fn bindings(&self, action: &str) -> &[InputBinding] 
+    { 
+        self.bindings.get(action).map_or(&[], |b| b.as_slice()) 
+    }
+
+    pub fn bind(&mut self, action: &str, binding: InputBinding)
+    {
+        let bindings = self.bindings.entry(action.into()).or_default();
+        if !bindings.contains(&binding)
+        {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str)
+    {
+        self.bindings.remove(action);
+    }
+
+    pub fn save(&self, path: &str)
+    {
+        let yaml = serde_yaml::to_string(self).expect("Could not serialize input map");
+
+        let mut file = File::create(path)
+            .expect(format!("Could not create file {}", path).as_str());
+
+        file.write_all(yaml.as_bytes())
+            .expect(format!("Could not write to file {}", path).as_str());
+    }
+
+    /// Returns the default map if there is no file at `path`, or it can't be read
+    pub fn load(path: &str) -> Self
+    {
+        let Ok(mut file) = File::open(path) else { return Self::default(); };
+
+        let mut yaml = String::new();
+        if let Err(e) = file.read_to_string(&mut yaml)
+        {
+            eprintln!("Could not read file {}, using the default bindings: {}", path, e);
+            return Self::default();
+        }
+
+        match serde_yaml::from_str(&yaml)
+        {
+            Ok(map) => map,
+            Err(e) => 
+            {
+                eprintln!("Could not deserialize input map from {}, using the default bindings: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+impl Default for InputMap
+{
+    fn default() -> Self 
+    {
+        let mut map = Self::new();
+        map.bind(actions::MOVE_FORWARD, InputBinding::Key(VirtualKeyCode::W));
+        map.bind(actions::MOVE_BACKWARD, InputBinding::Key(VirtualKeyCode::S));
+        map.bind(actions::MOVE_LEFT, InputBinding::Key(VirtualKeyCode::A));
+        map.bind(actions::MOVE_RIGHT, InputBinding::Key(VirtualKeyCode::D));
+        map.bind(actions::MOVE_UP, InputBinding::Key(VirtualKeyCode::Space));
+        map.bind(actions::MOVE_DOWN, InputBinding::Key(VirtualKeyCode::LShift));
+        map.bind(actions::SPRINT, InputBinding::Key(VirtualKeyCode::LControl));
+        map.bind(actions::SPEED_UP, InputBinding::Key(VirtualKeyCode::Equals));
+        map.bind(actions::SPEED_UP, InputBinding::Key(VirtualKeyCode::NumpadAdd));
+        map.bind(actions::SPEED_DOWN, InputBinding::Key(VirtualKeyCode::Minus));
+        map.bind(actions::SPEED_DOWN, InputBinding::Key(VirtualKeyCode::NumpadSubtract));
+        map.bind(actions::TOGGLE_CAMERA_MODE, InputBinding::Key(VirtualKeyCode::C));
+        map.bind(actions::TOGGLE_FLY, InputBinding::Key(VirtualKeyCode::F));
+        map
+    }
+}
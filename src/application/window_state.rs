@@ -0,0 +1,86 @@
+use std::{fs::File, io::{Write, Read}};
+
+use serde::{Serialize, Deserialize};
+use winit::{dpi::{PhysicalSize, PhysicalPosition}, event_loop::EventLoop};
+
+use super::WinitWindow;
+
+pub const DEFAULT_SAVE_PATH: &str = "window_state.yaml";
+
+/// The window geometry, saved on close so the next run opens in the same place
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowState
+{
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+    pub maximized: bool
+}
+
+impl WindowState
+{
+    /// Returns `None` if the window position can't be queried on this platform
+    pub fn from_window(window: &WinitWindow) -> Option<Self>
+    {
+        let size = window.inner_size();
+        let position = window.outer_position().ok()?;
+
+        Some(Self 
+        {
+            width: size.width,
+            height: size.height,
+            x: position.x,
+            y: position.y,
+            maximized: window.is_maximized()
+        })
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> { PhysicalSize::new(self.width, self.height) }
+    pub fn position(&self) -> PhysicalPosition<i32> { PhysicalPosition::new(self.x, self.y) }
+
+    /// Whether the top left corner of the window lies on one of the connected monitors
+    pub fn is_on_screen<T>(&self, event_loop: &EventLoop<T>) -> bool
+    {
+        event_loop.available_monitors().any(|monitor| 
+        {
+            let min = monitor.position();
+            let size = monitor.size();
+            self.x >= min.x && self.y >= min.y && 
+            self.x < min.x + size.width as i32 && self.y < min.y + size.height as i32
+        })
+    }
+
+    pub fn save(&self, path: &str)
+    {
+        let yaml = serde_yaml::to_string(self).expect("Could not serialize window state");
+
+        let mut file = File::create(path)
+            .expect(format!("Could not create file {}", path).as_str());
+
+        file.write_all(yaml.as_bytes())
+            .expect(format!("Could not write to file {}", path).as_str());
+    }
+
+    /// `None` if there is no file at `path`, or it can't be read
+    pub fn load(path: &str) -> Option<Self>
+    {
+        let mut file = File::open(path).ok()?;
+        let mut yaml = String::new();
+        if let Err(e) = file.read_to_string(&mut yaml)
+        {
+            eprintln!("Could not read file {}, using the default window state: {}", path, e);
+            return None;
+        }
+
+        match serde_yaml::from_str(&yaml)
+        {
+            Ok(state) => Some(state),
+            Err(e) => 
+            {
+                eprintln!("Could not deserialize window state from {}, using the default: {}", path, e);
+                None
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use cgmath::{Quaternion, Rotation, Rotation3, EuclideanSpace, Array, InnerSpace, Deg};
-use winit::event::{VirtualKeyCode};
+use cgmath::{Quaternion, Rotation, Rotation3, EuclideanSpace, Array, InnerSpace, Deg, Rad};
+use gilrs::Axis;
 
-use crate::{math::*, application::input::FrameState};
+use crate::{math::*, application::{input::FrameState, input_map::{InputMap, actions}}};
 
 #[derive(Debug, Clone)]
 pub struct Camera 
@@ -17,12 +17,56 @@ pub struct Camera
 
 impl Camera 
 {
+    /// Depth precision depends on `far / near`, so keep it as small as the view distance allows
+    pub const DEFAULT_NEAR: f32 = 0.1;
+    pub const DEFAULT_FAR: f32 = 1000.0;
+
+    pub fn set_clip_planes(&mut self, near: f32, far: f32)
+    {
+        assert!(near.is_finite() && far.is_finite() && near > 0.0 && far > near, "Clip planes must be finite and satisfy 0 < near < far");
+        self.near = near;
+        self.far = far;
+    }
+
     pub fn build_view_projection_matrix(&self) -> Mat4x4<f32>
     {
         let view = Mat4x4::look_at_rh(self.eye, self.target, self.up);
         let proj = cgmath::perspective(cgmath::Deg(self.fov), self.aspect, self.near, self.far);
         OPENGL_TO_WGPU_MATRIX * proj * view
     }
+
+    /// Where `point` lands on a `viewport` sized screen, with y going down from the top left. 
+    /// `None` when the point is behind the camera, points outside the view are still given
+    pub fn world_to_screen(&self, point: Point3D<f32>, viewport: Vec2<f32>) -> Option<Vec2<f32>>
+    {
+        let clip = self.build_view_projection_matrix() * point.to_homogeneous();
+        if clip.w <= 0.0
+        {
+            return None;
+        }
+
+        let ndc = Vec2::new(clip.x / clip.w, clip.y / clip.w);
+        Some(Vec2::new((ndc.x + 1.0) * 0.5 * viewport.x, (1.0 - ndc.y) * 0.5 * viewport.y))
+    }
+
+    /// The left, right, bottom, top, near and far planes, all facing inwards
+    pub fn frustum(&self) -> [Plane; 6]
+    {
+        use cgmath::Matrix;
+
+        let view_proj = self.build_view_projection_matrix();
+        let [r0, r1, r2, r3] = [view_proj.row(0), view_proj.row(1), view_proj.row(2), view_proj.row(3)];
+
+        // wgpu clip space depth goes from 0 to 1, so the near plane is just the z row
+        [
+            Plane::from_coefficients(r3 + r0),
+            Plane::from_coefficients(r3 - r0),
+            Plane::from_coefficients(r3 + r1),
+            Plane::from_coefficients(r3 - r1),
+            Plane::from_coefficients(r2),
+            Plane::from_coefficients(r3 - r2),
+        ]
+    }
 }
 
 #[repr(C)]
@@ -48,14 +92,34 @@ impl CameraUniform {
 unsafe impl bytemuck::Pod for CameraUniform {}
 unsafe impl bytemuck::Zeroable for CameraUniform {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode
+{
+    /// Free look, turning around the eye
+    FirstPerson,
+    /// Turns the eye around the target, scrolling moves closer or further away
+    Orbit
+}
+
 #[derive(Debug, Clone)]
 pub struct CameraEntity
 {
+    /// Where input has moved the camera to
     camera: Camera,
+    /// What is actually looked through, which trails `camera` when smoothing
+    view: Camera,
+    /// Roughly how many seconds the view takes to catch up, 0 snaps straight to the input
+    smoothing: f32,
+    snap_view: bool,
     speed: f32,
     turn_rate: f32,
     current_vertical_look: f32,
-    max_vertical_look: f32
+    max_vertical_look: f32,
+    mouse_look: bool,
+    sprinting: bool,
+    mode: CameraMode,
+    /// Whether up/down input moves the camera, walking leaves the height to whatever is controlling it
+    fly: bool
 }
 
 impl CameraEntity
@@ -64,51 +128,233 @@ impl CameraEntity
     {
         CameraEntity 
         {
+            view: camera.clone(),
             camera, 
+            smoothing: 0.0,
+            snap_view: false,
             speed, 
             turn_rate,
             current_vertical_look: 0.0,
-            max_vertical_look
+            max_vertical_look,
+            mouse_look: false,
+            sprinting: false,
+            mode: CameraMode::FirstPerson,
+            fly: true
+        }
+    }
+
+    pub const MIN_SPEED: f32 = 0.5;
+    pub const MAX_SPEED: f32 = 500.0;
+    pub const SPRINT_MULTIPLIER: f32 = 4.0;
+    /// How much one press of speed up or down scales the speed by
+    const SPEED_STEP: f32 = 1.25;
+
+    /// The base speed, without sprinting
+    pub fn speed(&self) -> f32 { self.speed }
+    pub fn is_sprinting(&self) -> bool { self.sprinting }
+
+    pub fn set_speed(&mut self, speed: f32)
+    {
+        self.speed = speed.clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+    }
+
+    pub fn camera(&self) -> &Camera {&self.view}
+
+    /// Changes made here aren't smoothed, so teleporting doesn't glide across the world
+    pub fn mut_camera(&mut self) -> &mut Camera 
+    {
+        self.snap_view = true;
+        &mut self.camera
+    }
+
+    pub fn is_flying(&self) -> bool { self.fly }
+    pub fn set_flying(&mut self, fly: bool) { self.fly = fly; }
+
+    pub fn mode(&self) -> CameraMode { self.mode }
+
+    /// Orbiting starts around the point `ORBIT_DISTANCE` in front of the camera
+    pub fn set_mode(&mut self, mode: CameraMode)
+    {
+        if mode == self.mode
+        {
+            return;
+        }
+
+        let look = (self.camera.target - self.camera.eye).normalize();
+        match mode
+        {
+            CameraMode::Orbit => self.camera.target = self.camera.eye + look * Self::ORBIT_DISTANCE,
+            CameraMode::FirstPerson => 
+            {
+                // carry on looking the same way, rotate_camera rebuilds the target from the pitch
+                self.camera.target = self.camera.eye + look;
+                self.current_vertical_look = (-Deg::from(Rad(look.y.asin())).0).clamp(-self.max_vertical_look, self.max_vertical_look);
+            }
         }
+
+        self.mode = mode;
     }
 
-    pub fn camera(&self) -> &Camera {&self.camera}
-    pub fn mut_camera(&mut self) -> &mut Camera {&mut self.camera}
-    pub fn update(&mut self, frame_state: &FrameState)
+    pub const ORBIT_DISTANCE: f32 = 10.0;
+    pub const MIN_ORBIT_DISTANCE: f32 = 0.5;
+    pub const MAX_ORBIT_DISTANCE: f32 = 1000.0;
+
+    pub fn smoothing(&self) -> f32 { self.smoothing }
+
+    pub fn set_smoothing(&mut self, smoothing: f32)
     {
-        self.rotate_camera(frame_state);
-        self.move_camera(frame_state);
+        self.smoothing = smoothing.max(0.0);
     }
+    
+    /// Whether mouse movement turns the camera, only wanted while the cursor is captured
+    pub fn set_mouse_look(&mut self, enabled: bool) { self.mouse_look = enabled; }
+    pub fn update(&mut self, frame_state: &FrameState, input_map: &InputMap)
+    {
+        if frame_state.is_action_pressed(input_map, actions::TOGGLE_CAMERA_MODE)
+        {
+            self.set_mode(match self.mode 
+            {
+                CameraMode::FirstPerson => CameraMode::Orbit,
+                CameraMode::Orbit => CameraMode::FirstPerson
+            });
+        }
 
-    fn move_camera(&mut self, frame_state: &FrameState)
+        match self.mode
+        {
+            CameraMode::FirstPerson =>
+            {
+                self.rotate_camera(frame_state);
+                self.move_camera(frame_state, input_map);
+                self.zoom_camera(frame_state);
+            },
+            CameraMode::Orbit =>
+            {
+                self.orbit_camera(frame_state);
+                self.move_camera(frame_state, input_map);
+            }
+        }
+
+        self.update_view(frame_state.delta_time());
+    }
+
+    /// Catches the view up to the camera without taking any input, for frames where the input goes somewhere else.
+    /// Without this, edits through `mut_camera` wouldn't show until input moved the camera again
+    pub fn update_view(&mut self, delta_time: f32)
+    {
+        self.smooth_view(delta_time);
+    }
+
+    /// The orbit distance after scrolling `scroll_delta` lines, kept between `MIN_ORBIT_DISTANCE` and `MAX_ORBIT_DISTANCE`
+    fn zoom_orbit_distance(distance: f32, scroll_delta: f32) -> f32
     {
+        const ZOOM_STEP: f32 = 0.9; // distance scale per line scrolled
+        (distance * ZOOM_STEP.powf(scroll_delta)).clamp(Self::MIN_ORBIT_DISTANCE, Self::MAX_ORBIT_DISTANCE)
+    }
+
+    fn orbit_camera(&mut self, frame_state: &FrameState)
+    {
+        let look_delta = self.look_delta(frame_state);
+        let offset = self.camera.eye - self.camera.target;
+        let distance = Self::zoom_orbit_distance(offset.magnitude(), frame_state.scroll_delta());
+
+        let yaw = offset.x.atan2(offset.z) - (look_delta.x * self.turn_rate * frame_state.delta_time()).to_radians();
+        let max_pitch = self.max_vertical_look.to_radians();
+        let pitch = ((offset.y / offset.magnitude()).asin() + (look_delta.y * self.turn_rate * frame_state.delta_time()).to_radians()).clamp(-max_pitch, max_pitch);
+
+        let offset = Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos()) * distance;
+        self.camera.eye = self.camera.target + offset;
+    }
+
+    fn smooth_view(&mut self, delta_time: f32)
+    {
+        // frame rate independent, and never past the input however long the frame was
+        let t = if self.smoothing <= 0.0 || self.snap_view { 1.0 } else { (1.0 - (-delta_time / self.smoothing).exp()).clamp(0.0, 1.0) };
+        self.snap_view = false;
+
+        if t >= 1.0
+        {
+            self.view = self.camera.clone();
+            return;
+        }
+
+        let goal_look = self.camera.target - self.camera.eye;
+        let view_look = (self.view.target - self.view.eye).normalize();
+        let blended = view_look * (1.0 - t) + goal_look.normalize() * t;
+
+        // looking the opposite way there is no arc to follow, so just turn
+        let look = if blended.magnitude2() > 1e-6 { blended.normalize() * goal_look.magnitude() } else { goal_look };
+
+        let eye = self.view.eye + (self.camera.eye - self.view.eye) * t;
+        self.view = Camera { eye, target: eye + look, ..self.camera.clone() };
+    }
+
+    fn zoom_camera(&mut self, frame_state: &FrameState)
+    {
+        const ZOOM_SPEED: f32 = 5.0; // degrees per line scrolled
+        const MIN_FOV: f32 = 10.0;
+        const MAX_FOV: f32 = 110.0;
+
+        self.camera.fov = (self.camera.fov - frame_state.scroll_delta() * ZOOM_SPEED).clamp(MIN_FOV, MAX_FOV);
+    }
+
+    fn move_camera(&mut self, frame_state: &FrameState, input_map: &InputMap)
+    {
+        if frame_state.is_action_pressed(input_map, actions::SPEED_UP) { self.set_speed(self.speed * Self::SPEED_STEP); }
+        if frame_state.is_action_pressed(input_map, actions::SPEED_DOWN) { self.set_speed(self.speed / Self::SPEED_STEP); }
+        self.sprinting = frame_state.is_action_down(input_map, actions::SPRINT);
+
+        if frame_state.is_action_pressed(input_map, actions::TOGGLE_FLY) { self.fly = !self.fly; }
+
         let forward = -(Vec3::new(self.camera.eye.x, 0.0, self.camera.eye.z) - Vec3::new(self.camera.target.x, 0.0, self.camera.target.z)).normalize();
         let right = Quaternion::from_angle_y(Deg(90.0)).rotate_vector(forward).normalize();
 
         let mut move_dir = Vec3::from_value(0.0);
 
-        if frame_state.is_key_down(VirtualKeyCode::W) { move_dir += forward; }
-        if frame_state.is_key_down(VirtualKeyCode::S) { move_dir += -forward; }
-        if frame_state.is_key_down(VirtualKeyCode::A) { move_dir += right; }
-        if frame_state.is_key_down(VirtualKeyCode::D) { move_dir += -right; }
+        if frame_state.is_action_down(input_map, actions::MOVE_FORWARD) { move_dir += forward; }
+        if frame_state.is_action_down(input_map, actions::MOVE_BACKWARD) { move_dir += -forward; }
+        if frame_state.is_action_down(input_map, actions::MOVE_LEFT) { move_dir += right; }
+        if frame_state.is_action_down(input_map, actions::MOVE_RIGHT) { move_dir += -right; }
 
-        if frame_state.is_key_down(VirtualKeyCode::Space) { move_dir.y += 1.0; }
-        if frame_state.is_key_down(VirtualKeyCode::LShift) { move_dir.y += -1.0; }
+        if frame_state.is_action_down(input_map, actions::MOVE_UP) { move_dir.y += 1.0; }
+        if frame_state.is_action_down(input_map, actions::MOVE_DOWN) { move_dir.y += -1.0; }
+
+        move_dir += forward * frame_state.gamepad_axis(Axis::LeftStickY);
+        move_dir += -right * frame_state.gamepad_axis(Axis::LeftStickX);
+        if frame_state.is_gamepad_button_down(gilrs::Button::South) { move_dir.y += 1.0; }
+        if frame_state.is_gamepad_button_down(gilrs::Button::East) { move_dir.y += -1.0; }
+
+        if !self.fly
+        {
+            move_dir.y = 0.0;
+        }
 
         if move_dir.x != 0.0 || move_dir.y != 0.0 || move_dir.z != 0.0
         {
-            move_dir = move_dir.normalize() * frame_state.delta_time() * self.speed;
+            let speed = if self.sprinting { self.speed * Self::SPRINT_MULTIPLIER } else { self.speed };
+            move_dir = move_dir.normalize() * frame_state.delta_time() * speed;
         }
 
         self.camera.eye += move_dir;
         self.camera.target += move_dir;
     }
 
+    /// The mouse and right stick movement this frame, in pixels
+    fn look_delta(&self, frame_state: &FrameState) -> Vec2<f32>
+    {
+        // how many pixels of mouse movement a fully tilted stick is worth each frame
+        const GAMEPAD_LOOK_SCALE: f32 = 10.0;
+        let stick = Vec2::new(frame_state.gamepad_axis(Axis::RightStickX), -frame_state.gamepad_axis(Axis::RightStickY));
+        let mouse_delta = if self.mouse_look { frame_state.mouse_delta() } else { Vec2::new(0.0, 0.0) };
+        mouse_delta + stick * GAMEPAD_LOOK_SCALE
+    }
+
     fn rotate_camera(&mut self, frame_state: &FrameState)
     {
-        self.current_vertical_look = (self.current_vertical_look + frame_state.mouse_delta().y * self.turn_rate * frame_state.delta_time()).clamp(-self.max_vertical_look, self.max_vertical_look);
+        let look_delta = self.look_delta(frame_state);
+
+        self.current_vertical_look = (self.current_vertical_look + look_delta.y * self.turn_rate * frame_state.delta_time()).clamp(-self.max_vertical_look, self.max_vertical_look);
 
-        let horizontal_rotation = Quaternion::from_angle_y(Deg(-frame_state.mouse_delta().x * self.turn_rate * frame_state.delta_time()));
+        let horizontal_rotation = Quaternion::from_angle_y(Deg(-look_delta.x * self.turn_rate * frame_state.delta_time()));
 
         let forward = -(Vec3::new(self.camera.eye.x, 0.0, self.camera.eye.z) - Vec3::new(self.camera.target.x, 0.0, self.camera.target.z)).normalize();
         let right = Quaternion::from_angle_y(Deg(90.0)).rotate_vector(forward).normalize();
@@ -121,4 +367,113 @@ impl CameraEntity
         let target_vec = target_relative + self.camera.eye.to_vec();
         self.camera.target = Point3D::new(target_vec.x, target_vec.y, target_vec.z);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn test_camera() -> Camera
+    {
+        Camera 
+        { 
+            eye: Point3D::new(0.0, 0.0, 0.0), 
+            target: Point3D::new(0.0, 0.0, -1.0), 
+            up: Vec3::unit_y(), 
+            aspect: 1.0, 
+            fov: 90.0, 
+            near: Camera::DEFAULT_NEAR, 
+            far: Camera::DEFAULT_FAR 
+        }
+    }
+
+    #[test]
+    fn camera_edits_snap_the_view_without_input()
+    {
+        let mut entity = CameraEntity::new(test_camera(), 10.0, 90.0, 89.0);
+        entity.set_smoothing(1.0);
+
+        entity.mut_camera().eye = Point3D::new(100.0, 0.0, 0.0);
+        entity.mut_camera().set_clip_planes(1.0, 50.0);
+        entity.update_view(1.0 / 60.0);
+
+        assert_eq!(entity.camera().eye, Point3D::new(100.0, 0.0, 0.0));
+        assert_eq!((entity.camera().near, entity.camera().far), (1.0, 50.0));
+    }
+
+    #[test]
+    fn projection_maps_near_and_far_to_the_depth_range()
+    {
+        let mut camera = test_camera();
+        camera.set_clip_planes(0.5, 200.0);
+        let view_proj = camera.build_view_projection_matrix();
+
+        let depth = |distance: f32| 
+        {
+            let clip = view_proj * Point3D::new(0.0, 0.0, -distance).to_homogeneous();
+            clip.z / clip.w
+        };
+
+        assert!(depth(camera.near).abs() < 1e-5, "{}", depth(camera.near));
+        assert!((depth(camera.far) - 1.0).abs() < 1e-5, "{}", depth(camera.far));
+        assert!(depth(10.0) > 0.0 && depth(10.0) < 1.0);
+    }
+
+    #[test]
+    fn orbit_zoom_is_clamped()
+    {
+        assert_eq!(CameraEntity::zoom_orbit_distance(10.0, 0.0), 10.0);
+        assert!(CameraEntity::zoom_orbit_distance(10.0, 1.0) < 10.0);
+        assert!(CameraEntity::zoom_orbit_distance(10.0, -1.0) > 10.0);
+
+        assert_eq!(CameraEntity::zoom_orbit_distance(1.0, 1000.0), CameraEntity::MIN_ORBIT_DISTANCE);
+        assert_eq!(CameraEntity::zoom_orbit_distance(100.0, -1000.0), CameraEntity::MAX_ORBIT_DISTANCE);
+    }
+
+    #[test]
+    fn orbiting_starts_at_the_orbit_distance()
+    {
+        let mut entity = CameraEntity::new(test_camera(), 10.0, 90.0, 89.0);
+        entity.set_mode(CameraMode::Orbit);
+
+        let camera = entity.mut_camera();
+        assert!(((camera.target - camera.eye).magnitude() - CameraEntity::ORBIT_DISTANCE).abs() < 1e-4);
+    }
+
+    #[test]
+    fn world_to_screen_puts_the_target_in_the_center()
+    {
+        let camera = test_camera();
+        let viewport = Vec2::new(800.0, 600.0);
+
+        let center = camera.world_to_screen(Point3D::new(0.0, 0.0, -5.0), viewport).unwrap();
+        assert!((center.x - 400.0).abs() < 1e-3 && (center.y - 300.0).abs() < 1e-3, "{:?}", center);
+
+        // y goes down the screen
+        let above = camera.world_to_screen(Point3D::new(0.0, 1.0, -5.0), viewport).unwrap();
+        assert!(above.y < 300.0);
+
+        assert!(camera.world_to_screen(Point3D::new(0.0, 0.0, 5.0), viewport).is_none());
+    }
+
+    #[test]
+    fn box_behind_the_camera_is_culled()
+    {
+        let frustum = test_camera().frustum();
+
+        let behind = AABB::new(Point3D::new(-1.0, -1.0, 5.0), Point3D::new(1.0, 1.0, 6.0));
+        assert!(!behind.intersects_frustum(&frustum));
+
+        let in_front = AABB::new(Point3D::new(-1.0, -1.0, -6.0), Point3D::new(1.0, 1.0, -5.0));
+        assert!(in_front.intersects_frustum(&frustum));
+    }
+
+    #[test]
+    fn box_straddling_the_near_plane_is_kept()
+    {
+        let camera = test_camera();
+        let straddling = AABB::new(Point3D::new(-0.5, -0.5, -camera.near - 0.5), Point3D::new(0.5, 0.5, -camera.near + 0.5));
+        assert!(straddling.intersects_frustum(&camera.frustum()));
+    }
+}
@@ -1,6 +1,8 @@
 pub mod bind_group;
 pub mod buffer;
 pub mod texture;
+pub mod staging_pool;
+pub mod gpu_timer;
 use std::sync::Arc;
 
 use crate::math::{Vec4, Vec2};
@@ -9,52 +11,124 @@ use crate::{utils::Byteable, math::Vec3};
 pub use self::bind_group::*;
 pub use self::buffer::*;
 pub use self::texture::*;
+pub use self::staging_pool::*;
+pub use self::gpu_timer::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WgpuOptions
+{
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    pub force_fallback: bool
+}
+
+impl Default for WgpuOptions
+{
+    fn default() -> Self 
+    {
+        Self 
+        { 
+            backends: wgpu::Backends::all(), 
+            power_preference: wgpu::PowerPreference::default(), 
+            force_fallback: false 
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WgpuInitError
+{
+    SurfaceCreationFailed(wgpu::CreateSurfaceError),
+    /// Holds every adapter found on the requested backends, none of which were compatible
+    NoAdapter { backends: wgpu::Backends, available: Vec<wgpu::AdapterInfo> },
+    DeviceRequestFailed(wgpu::RequestDeviceError)
+}
+
+impl std::fmt::Display for WgpuInitError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result 
+    {
+        match self
+        {
+            WgpuInitError::SurfaceCreationFailed(e) => write!(f, "Could not create a surface for the window: {}", e),
+            WgpuInitError::NoAdapter { backends, available } => 
+            {
+                write!(f, "Could not find a compatible graphics adapter on backends {:?}", backends)?;
+                if available.is_empty()
+                {
+                    write!(f, ", no adapters were found at all")
+                }
+                else 
+                {
+                    write!(f, ", found:")?;
+                    for info in available
+                    {
+                        write!(f, "\n  {} ({:?}, {:?})", info.name, info.backend, info.device_type)?;
+                    }
+                    Ok(())
+                }
+            },
+            WgpuInitError::DeviceRequestFailed(e) => write!(f, "Could not create a graphics device: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WgpuInitError {}
 
 pub struct WgpuState
 {
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
-    surface: Arc<wgpu::Surface>,
-    surface_config: wgpu::SurfaceConfiguration
+    /// `None` when headless
+    surface: Option<Arc<wgpu::Surface>>,
+    surface_config: wgpu::SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
+    /// MSAA sample counts usable with both the surface format and the depth format, always includes 1
+    sample_counts: Vec<u32>,
+    adapter_info: wgpu::AdapterInfo
 }
 
 impl WgpuState
 {
     pub fn device(&self) -> &Arc<wgpu::Device> { &self.device }
     pub fn queue(&self) -> &Arc<wgpu::Queue> { &self.queue }
-    pub fn surface(&self) -> &Arc<wgpu::Surface> { &self.surface }
+    pub fn surface(&self) -> Option<&Arc<wgpu::Surface>> { self.surface.as_ref() }
     pub fn surface_config(&self) -> &wgpu::SurfaceConfiguration { &self.surface_config }
+    pub fn present_mode(&self) -> wgpu::PresentMode { self.surface_config.present_mode }
+    pub fn supported_present_modes(&self) -> &[wgpu::PresentMode] { &self.present_modes }
+    pub fn supported_sample_counts(&self) -> &[u32] { &self.sample_counts }
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo { &self.adapter_info }
 
-    pub async fn new(window: &winit::window::Window) -> Self 
+    pub async fn new(window: &winit::window::Window) -> Result<Self, WgpuInitError> 
+    {
+        Self::new_with_options(window, WgpuOptions::default()).await
+    }
+
+    pub async fn new_with_options(window: &winit::window::Window, options: WgpuOptions) -> Result<Self, WgpuInitError> 
     {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
+            backends: options.backends,
             dx12_shader_compiler: Default::default()
         });
 
-        let surface = unsafe {instance.create_surface(&window)}.unwrap();
+        let surface = unsafe {instance.create_surface(&window)}
+            .map_err(WgpuInitError::SurfaceCreationFailed)?;
 
         let adapter = instance.request_adapter(
             &wgpu::RequestAdapterOptions 
             { 
-                power_preference: wgpu::PowerPreference::default(), 
+                power_preference: options.power_preference, 
                 compatible_surface: Some(&surface), 
-                force_fallback_adapter: false
+                force_fallback_adapter: options.force_fallback
             }
-        ).await.unwrap();
-
-        println!("Name: {:?}\nBackend: {:?}", adapter.get_info().name, adapter.get_info().backend);
-
-        let features = wgpu::Features::empty();
+        ).await.ok_or_else(|| WgpuInitError::NoAdapter 
+        { 
+            backends: options.backends, 
+            available: instance.enumerate_adapters(options.backends).map(|a| a.get_info()).collect() 
+        })?;
 
-        let (device, queue) = adapter.request_device( 
-            &wgpu::DeviceDescriptor
-            {
-                features,
-                limits: wgpu::Limits::default(),
-                label: None
-            }, None).await.unwrap();
+        let (device, queue, adapter_info) = Self::request_device(&adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -63,8 +137,11 @@ impl WgpuState
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
 
+        // copying out of the swapchain is needed for screenshots, but not every surface allows it
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC);
+
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format: surface_format,
             width: size.width,
             height: size.height,
@@ -75,16 +152,134 @@ impl WgpuState
 
         surface.configure(&device, &config);
 
+        let sample_counts = Self::sample_counts(&adapter, &device, config.format);
+
         let device = Arc::new(device);
         let queue = Arc::new(queue);
-        let surface = Arc::new(surface);
+        let surface = Some(Arc::new(surface));
 
-        Self
+        Ok(Self
         {
             device,
             queue,
             surface,
-            surface_config: config
+            surface_config: config,
+            present_modes: surface_caps.present_modes,
+            sample_counts,
+            adapter_info
+        })
+    }
+
+    /// Without a window or surface, for rendering offscreen into a `RenderTarget`.
+    /// `surface_config` still describes the target size and format, so the render stages can be built from it.
+    pub async fn new_headless(size: Vec2<u32>, options: WgpuOptions) -> Result<Self, WgpuInitError>
+    {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: options.backends,
+            dx12_shader_compiler: Default::default()
+        });
+
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions 
+            { 
+                power_preference: options.power_preference, 
+                compatible_surface: None, 
+                force_fallback_adapter: options.force_fallback
+            }
+        ).await.ok_or_else(|| WgpuInitError::NoAdapter 
+        { 
+            backends: options.backends, 
+            available: instance.enumerate_adapters(options.backends).map(|a| a.get_info()).collect() 
+        })?;
+
+        let (device, queue, adapter_info) = Self::request_device(&adapter).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: size.x.max(1),
+            height: size.y.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![]
+        };
+
+        let sample_counts = Self::sample_counts(&adapter, &device, config.format);
+
+        Ok(Self
+        {
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            surface: None,
+            surface_config: config,
+            present_modes: vec![wgpu::PresentMode::Fifo],
+            sample_counts,
+            adapter_info
+        })
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue, wgpu::AdapterInfo), WgpuInitError>
+    {
+        let adapter_info = adapter.get_info();
+        println!("Name: {:?}\nBackend: {:?}\nDevice type: {:?}", adapter_info.name, adapter_info.backend, adapter_info.device_type);
+
+        // only request optional features the adapter actually has, so that request_device doesn't fail
+        let features = adapter.features() & (wgpu::Features::PUSH_CONSTANTS | wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let limits = wgpu::Limits {
+            max_push_constant_size: adapter.limits().max_push_constant_size,
+            ..Default::default()
+        };
+
+        let (device, queue) = adapter.request_device( 
+            &wgpu::DeviceDescriptor
+            {
+                features,
+                limits,
+                label: None
+            }, None).await.map_err(WgpuInitError::DeviceRequestFailed)?;
+
+        Ok((device, queue, adapter_info))
+    }
+
+    /// Without `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` only the guaranteed counts of 1 and 4 can be used
+    fn sample_counts(adapter: &wgpu::Adapter, device: &wgpu::Device, format: wgpu::TextureFormat) -> Vec<u32>
+    {
+        let format_features = |format: wgpu::TextureFormat| {
+            if device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            {
+                adapter.get_texture_format_features(format).flags
+            }
+            else 
+            {
+                format.guaranteed_format_features(device.features()).flags
+            }
+        };
+
+        let color = format_features(format);
+        let depth = format_features(Texture::DEPTH_FORMAT);
+
+        [1, 2, 4, 8].into_iter()
+            .filter(|count| *count == 1 || (color.sample_count_supported(*count) && depth.sample_count_supported(*count)))
+            .collect()
+    }
+
+    /// Falls back to `Fifo`, which every surface supports, if `present_mode` isn't supported
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode)
+    {
+        let present_mode = if self.present_modes.contains(&present_mode)
+        {
+            present_mode
+        }
+        else 
+        {
+            eprintln!("Warning: present mode {:?} is not supported, falling back to {:?}", present_mode, wgpu::PresentMode::Fifo);
+            wgpu::PresentMode::Fifo
+        };
+
+        if self.surface_config.present_mode != present_mode
+        {
+            self.surface_config.present_mode = present_mode;
+            self.configure_surface();
         }
     }
 
@@ -94,8 +289,16 @@ impl WgpuState
         {
             self.surface_config.width = size.x;
             self.surface_config.height = size.y;
+            self.configure_surface();
+        }
+    }
+
+    fn configure_surface(&self)
+    {
+        if let Some(surface) = &self.surface
+        {
             self.device.poll(wgpu::MaintainBase::Wait); // to fix crash on dx12 with wgpu 0.17
-            self.surface.configure(&self.device, &self.surface_config);
+            surface.configure(&self.device, &self.surface_config);
         }
     }
 }
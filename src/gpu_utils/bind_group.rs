@@ -101,6 +101,12 @@ impl<T> Uniform<T> where T : Byteable
     {
         self.buffer.enqueue_write(&[value], queue);
     }
+
+    /// Copies the element at `index` of `source` into this uniform, ordered with the other commands in `command_encoder`
+    pub fn copy_from(&mut self, source: &GBuffer<T>, index: u64, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        source.copy_region(index, &mut self.buffer, 0, 1, command_encoder);
+    }
 }
 
 impl<T> Entry for Uniform<T> where T : Byteable
@@ -141,6 +147,7 @@ pub struct Storage<T> where T : Byteable
 
 impl<T> Storage<T> where T : Byteable
 {
+    pub fn buffer(&self) -> &GBuffer<T> { &self.buffer }
     pub fn length(&self) -> u64 { self.buffer.length() }
     pub fn capacity(&self) -> u64 { self.buffer.capacity() }
 
@@ -197,6 +204,12 @@ impl<T> Storage<T> where T : Byteable
     {
         self.buffer.enqueue_write(data, queue);
     }
+
+    /// Returns true if the buffer had to be reallocated, in which case the bind groups using it need to be rebuilt
+    pub fn enqueue_write_or_grow(&mut self, data: &[T], device: &wgpu::Device, queue: &wgpu::Queue) -> bool
+    {
+        self.buffer.enqueue_write_or_grow(data, device, queue)
+    }
 }
 
 impl<T> Entry for Storage<T> where T : Byteable
@@ -242,6 +255,9 @@ pub struct MappedBuffer<T> where T : Byteable
 
 impl<T> MappedBuffer<T> where T : Byteable
 {
+    pub fn length(&self) -> u64 { self.buffer.length() }
+    pub fn capacity(&self) -> u64 { self.buffer.capacity() }
+
     pub fn buffer_usage() -> wgpu::BufferUsages 
     {
         wgpu::BufferUsages::COPY_DST | 
@@ -279,6 +295,21 @@ impl<T> MappedBuffer<T> where T : Byteable
     {
         self.buffer.read(device)
     }
+
+    pub fn read_range(&self, device: &wgpu::Device, start: u64, count: u64) -> Vec<T>
+    {
+        self.buffer.read_range(device, start, count)
+    }
+
+    pub fn copy_from_texture(&mut self, texture: &wgpu::Texture, bytes_per_row: u32, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        self.buffer.copy_from_texture(texture, bytes_per_row, command_encoder);
+    }
+
+    pub fn copy_from(&mut self, source: &GBuffer<T>, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        source.copy(&mut self.buffer, command_encoder);
+    }
 }
 
 impl<T> Entry for MappedBuffer<T> where T : Byteable
@@ -314,4 +345,128 @@ impl<T> Entry for MappedBuffer<T> where T : Byteable
             count: None 
         }
     }
-}
\ No newline at end of file
+}
+
+pub struct TextureEntry<'a>
+{
+    view: &'a wgpu::TextureView,
+    sample_type: wgpu::TextureSampleType,
+    view_dimension: wgpu::TextureViewDimension,
+    visibility: wgpu::ShaderStages
+}
+
+impl<'a> TextureEntry<'a>
+{
+    pub fn new(view: &'a wgpu::TextureView, visibility: wgpu::ShaderStages) -> Self
+    {
+        Self::with_sample_type(view, wgpu::TextureSampleType::Float { filterable: true }, wgpu::TextureViewDimension::D2, visibility)
+    }
+
+    pub fn with_sample_type(view: &'a wgpu::TextureView, sample_type: wgpu::TextureSampleType, view_dimension: wgpu::TextureViewDimension, visibility: wgpu::ShaderStages) -> Self
+    {
+        Self 
+        { 
+            view, 
+            sample_type, 
+            view_dimension, 
+            visibility 
+        }
+    }
+}
+
+impl<'a> Entry for TextureEntry<'a>
+{
+    fn get_layout(&self, binding: u32) -> wgpu::BindGroupLayoutEntry 
+    {
+        wgpu::BindGroupLayoutEntry 
+        { 
+            binding, 
+            visibility: self.visibility, 
+            ty: wgpu::BindingType::Texture 
+            { 
+                sample_type: self.sample_type, 
+                view_dimension: self.view_dimension, 
+                multisampled: false 
+            }, 
+            count: None 
+        }
+    }
+
+    fn get_resource(&self) -> wgpu::BindingResource 
+    {
+        wgpu::BindingResource::TextureView(self.view)
+    }
+
+    fn get_layout_static(visibility: wgpu::ShaderStages, binding: u32) -> wgpu::BindGroupLayoutEntry
+        where Self : Sized 
+    {
+        wgpu::BindGroupLayoutEntry 
+        { 
+            binding, 
+            visibility, 
+            ty: wgpu::BindingType::Texture 
+            { 
+                sample_type: wgpu::TextureSampleType::Float { filterable: true }, 
+                view_dimension: wgpu::TextureViewDimension::D2, 
+                multisampled: false 
+            }, 
+            count: None 
+        }
+    }
+}
+
+pub struct SamplerEntry<'a>
+{
+    sampler: &'a wgpu::Sampler,
+    binding_type: wgpu::SamplerBindingType,
+    visibility: wgpu::ShaderStages
+}
+
+impl<'a> SamplerEntry<'a>
+{
+    pub fn new(sampler: &'a wgpu::Sampler, visibility: wgpu::ShaderStages) -> Self
+    {
+        Self::with_binding_type(sampler, wgpu::SamplerBindingType::Filtering, visibility)
+    }
+
+    pub fn with_binding_type(sampler: &'a wgpu::Sampler, binding_type: wgpu::SamplerBindingType, visibility: wgpu::ShaderStages) -> Self
+    {
+        Self 
+        { 
+            sampler, 
+            binding_type, 
+            visibility 
+        }
+    }
+}
+
+impl<'a> Entry for SamplerEntry<'a>
+{
+    fn get_layout(&self, binding: u32) -> wgpu::BindGroupLayoutEntry 
+    {
+        wgpu::BindGroupLayoutEntry 
+        { 
+            binding, 
+            visibility: self.visibility, 
+            ty: wgpu::BindingType::Sampler(self.binding_type), 
+            count: None 
+        }
+    }
+
+    fn get_resource(&self) -> wgpu::BindingResource 
+    {
+        wgpu::BindingResource::Sampler(self.sampler)
+    }
+
+    fn get_layout_static(visibility: wgpu::ShaderStages, binding: u32) -> wgpu::BindGroupLayoutEntry
+        where Self : Sized 
+    {
+        wgpu::BindGroupLayoutEntry 
+        { 
+            binding, 
+            visibility, 
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), 
+            count: None 
+        }
+    }
+}
@@ -60,15 +60,70 @@ impl<T> GBuffer<T> where T : Byteable
 
     pub fn enqueue_write(&mut self, data: &[T], queue: &wgpu::Queue)
     {
+        assert!(data.len() as u64 <= self.capacity, "Data is larger than the capacity of this buffer.");
         self.length = data.len() as u64;
         queue.write_buffer(&self.handle, 0, bytemuck::cast_slice(data));
     }
 
+    /// Overwrites the elements from `start` onwards, leaving the rest of the buffer as it is
+    pub fn enqueue_write_at(&mut self, start: u64, data: &[T], queue: &wgpu::Queue)
+    {
+        assert!(start + data.len() as u64 <= self.length, "Write is past the end of the contained data.");
+        queue.write_buffer(&self.handle, start * std::mem::size_of::<T>() as u64, bytemuck::cast_slice(data));
+    }
+
+    /// Writes `data` into the buffer, reallocating it first if it is too small.
+    /// Returns true if the buffer was reallocated, in which case any bind groups using it need to be rebuilt.
+    pub fn enqueue_write_or_grow(&mut self, data: &[T], device: &wgpu::Device, queue: &wgpu::Queue) -> bool
+    {
+        let grew = data.len() as u64 > self.capacity;
+        if grew
+        {
+            self.reserve((data.len() as u64).max(self.capacity * 2), device, None);
+        }
+
+        self.enqueue_write(data, queue);
+        grew
+    }
+
+    /// Reallocates the buffer so that it can hold at least `new_capacity` elements.
+    /// If a command encoder is given, the current contents are copied into the new buffer.
+    pub fn reserve(&mut self, new_capacity: u64, device: &wgpu::Device, command_encoder: Option<&mut wgpu::CommandEncoder>)
+    {
+        if new_capacity <= self.capacity
+        {
+            return;
+        }
+
+        let handle = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: new_capacity * std::mem::size_of::<T>() as u64,
+            usage: self.usage,
+            mapped_at_creation: false
+        });
+
+        match command_encoder
+        {
+            Some(command_encoder) =>
+            {
+                assert!(self.usage.contains(wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST), "Buffer must be COPY_SRC and COPY_DST to keep its contents when growing");
+                command_encoder.copy_buffer_to_buffer(&self.handle, 0, &handle, 0, self.size());
+            },
+            None => self.length = 0,
+        }
+
+        self.handle = handle;
+        self.capacity = new_capacity;
+    }
+
     pub fn slice(&self, start: u64, end: u64) -> wgpu::BufferSlice
     {
         assert!(start <= end, "Start index must be less than or equal to the end index");
-        assert!(end < self.length(), "Slice is larger than the contained data");
-        self.handle.slice((start * std::mem::size_of::<T>() as u64)..(end * std::mem::size_of::<T>() as u64))
+        assert!(end <= self.length(), "Slice is larger than the contained data");
+
+        // `start..end` is half open, so `slice(0, length)` covers the same range as `slice_all`
+        let element_size = std::mem::size_of::<T>() as u64;
+        self.handle.slice((start * element_size)..(end * element_size))
     }
 
     pub fn slice_all(&self) -> wgpu::BufferSlice
@@ -83,7 +138,31 @@ impl<T> GBuffer<T> where T : Byteable
 
     pub async fn read_async(&self, device: &wgpu::Device) -> Vec<T>
     {
-        let buffer_slice = self.slice_all();
+        self.read_range_async(device, 0, self.length()).await
+    }
+
+    pub fn read_range(&self, device: &wgpu::Device, start: u64, count: u64) -> Vec<T>
+    {
+        pollster::block_on(self.read_range_async(device, start, count))
+    }
+
+    /// Maps and reads back only the elements `start..(start + count)`
+    pub async fn read_range_async(&self, device: &wgpu::Device, start: u64, count: u64) -> Vec<T>
+    {
+        assert!(start + count <= self.length(), "Read range {}..{} is out of bounds of the buffer of length {}", start, start + count, self.length());
+        if count == 0
+        {
+            return vec![];
+        }
+
+        // the mapped range has to be aligned, so map a slightly larger range and cut the requested bytes out of it
+        let element_size = std::mem::size_of::<T>() as u64;
+        let byte_start = start * element_size;
+        let byte_end = (start + count) * element_size;
+        let map_start = byte_start - byte_start % wgpu::MAP_ALIGNMENT;
+        let map_end = (byte_end + wgpu::COPY_BUFFER_ALIGNMENT - 1) / wgpu::COPY_BUFFER_ALIGNMENT * wgpu::COPY_BUFFER_ALIGNMENT;
+
+        let buffer_slice = self.handle.slice(map_start..map_end);
         let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
         buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
 
@@ -93,7 +172,9 @@ impl<T> GBuffer<T> where T : Byteable
         {
             Some(Ok(())) => {
                 let data = buffer_slice.get_mapped_range();
-                let result = bytemuck::cast_slice(&data).to_vec();
+                let mut result = vec![T::zeroed(); count as usize];
+                let offset = (byte_start - map_start) as usize;
+                bytemuck::cast_slice_mut::<T, u8>(&mut result).copy_from_slice(&data[offset..(offset + (byte_end - byte_start) as usize)]);
     
                 drop(data);
                 self.handle.unmap();
@@ -116,6 +197,51 @@ impl<T> GBuffer<T> where T : Byteable
         dest.length = self.length;
     }
 
+    pub fn copy_region(&self, source_start: u64, dest: &mut GBuffer<T>, dest_start: u64, count: u64, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        assert!(source_start + count <= self.length(), "Source region is out of range");
+        assert!(dest_start + count <= dest.capacity, "Destination region is out of range");
+
+        let element_size = std::mem::size_of::<T>() as u64;
+        command_encoder.copy_buffer_to_buffer(&self.handle, source_start * element_size, &dest.handle, dest_start * element_size, count * element_size);
+    }
+
+    /// Copies all of `texture` into this buffer, `bytes_per_row` must be a multiple of `COPY_BYTES_PER_ROW_ALIGNMENT`
+    pub fn copy_from_texture(&mut self, texture: &wgpu::Texture, bytes_per_row: u32, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        let size = bytes_per_row as u64 * texture.height() as u64;
+        assert!(size % std::mem::size_of::<T>() as u64 == 0, "Texture size is not a multiple of the element size");
+        assert!(size / std::mem::size_of::<T>() as u64 <= self.capacity, "Buffer capacity not large enough for the texture");
+
+        command_encoder.copy_texture_to_buffer(
+            texture.as_image_copy(), 
+            wgpu::ImageCopyBuffer 
+            { 
+                buffer: &self.handle, 
+                layout: wgpu::ImageDataLayout 
+                { 
+                    offset: 0, 
+                    bytes_per_row: Some(bytes_per_row), 
+                    rows_per_image: Some(texture.height()) 
+                } 
+            }, 
+            texture.size()
+        );
+
+        self.length = size / std::mem::size_of::<T>() as u64;
+    }
+
+    /// Writes the results of `queries` to the start of this buffer, which must have `QUERY_RESOLVE` usage
+    pub fn resolve_query_set(&mut self, query_set: &wgpu::QuerySet, queries: std::ops::Range<u32>, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        let size = (queries.end - queries.start) as u64 * wgpu::QUERY_SIZE as u64;
+        assert!(size % std::mem::size_of::<T>() as u64 == 0, "Query results are not a multiple of the element size");
+        assert!(size / std::mem::size_of::<T>() as u64 <= self.capacity, "Buffer capacity not large enough for the queries");
+
+        command_encoder.resolve_query_set(query_set, queries, &self.handle, 0);
+        self.length = size / std::mem::size_of::<T>() as u64;
+    }
+
     pub fn as_entire_binding(&self) -> wgpu::BindingResource
     {
         self.handle.as_entire_binding()
@@ -135,7 +261,7 @@ pub struct VertexBuffer<T> where T : VertexData
 impl<T> VertexBuffer<T> where T : VertexData
 {
     pub fn buffer(&self) -> &GBuffer<T> { &self.buffer }
-    pub fn mut_buffer(&mut self) -> &GBuffer<T> { &mut self.buffer }
+    pub fn mut_buffer(&mut self) -> &mut GBuffer<T> { &mut self.buffer }
 
     pub fn length(&self) -> u64 { self.buffer.length() }
     pub fn capacity(&self) -> u64 { self.buffer.capacity() }
@@ -176,6 +302,16 @@ impl<T> VertexBuffer<T> where T : VertexData
     {
         self.buffer.enqueue_write(data, queue);
     }
+
+    pub fn enqueue_write_at(&mut self, start: u64, data: &[T], queue: &wgpu::Queue)
+    {
+        self.buffer.enqueue_write_at(start, data, queue);
+    }
+
+    pub fn enqueue_write_or_grow(&mut self, data: &[T], device: &wgpu::Device, queue: &wgpu::Queue) -> bool
+    {
+        self.buffer.enqueue_write_or_grow(data, device, queue)
+    }
 }
 
 pub struct IndexBuffer 
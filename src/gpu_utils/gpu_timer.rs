@@ -0,0 +1,60 @@
+use crate::gpu_utils::{GBuffer, MappedBuffer};
+
+/// Measures how long the gpu spends on the commands between `begin` and `end` with timestamp queries
+pub struct GpuTimer
+{
+    query_set: wgpu::QuerySet,
+    resolve_buffer: GBuffer<u64>,
+    read_buffer: MappedBuffer<u64>,
+    /// Nanoseconds per timestamp tick
+    period: f32
+}
+
+impl GpuTimer
+{
+    /// `None` if the device was created without `TIMESTAMP_QUERY`
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self>
+    {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY)
+        {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Timer Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2
+        });
+
+        let resolve_buffer = GBuffer::with_capacity(2, wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC, device, Some("Timer Resolve Buffer"));
+        let read_buffer = MappedBuffer::with_capacity(2, wgpu::ShaderStages::NONE, device);
+
+        Some(Self 
+        { 
+            query_set, 
+            resolve_buffer, 
+            read_buffer, 
+            period: queue.get_timestamp_period() 
+        })
+    }
+
+    pub fn begin(&self, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        command_encoder.write_timestamp(&self.query_set, 0);
+    }
+
+    /// Also copies the results somewhere they can be read once `command_encoder` has been submitted
+    pub fn end(&mut self, command_encoder: &mut wgpu::CommandEncoder)
+    {
+        command_encoder.write_timestamp(&self.query_set, 1);
+        self.resolve_buffer.resolve_query_set(&self.query_set, 0..2, command_encoder);
+        self.read_buffer.copy_from(&self.resolve_buffer, command_encoder);
+    }
+
+    /// In milliseconds, this blocks until the gpu has finished the timed commands
+    pub fn read(&self, device: &wgpu::Device) -> f32
+    {
+        let timestamps = self.read_buffer.read(device);
+        timestamps[1].wrapping_sub(timestamps[0]) as f32 * self.period / 1_000_000.0
+    }
+}
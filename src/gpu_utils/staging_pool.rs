@@ -0,0 +1,87 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use crate::utils::Byteable;
+
+use super::MappedBuffer;
+
+/// A pool of reusable `MappedBuffer`s, used for reading data back from the gpu without allocating a new buffer every time.
+pub struct StagingPool<T> where T : Byteable
+{
+    device: Arc<wgpu::Device>,
+    visibility: wgpu::ShaderStages,
+    free: Arc<Mutex<Vec<MappedBuffer<T>>>>
+}
+
+impl<T> StagingPool<T> where T : Byteable
+{
+    pub fn free_count(&self) -> usize { self.free.lock().unwrap().len() }
+
+    pub fn new(visibility: wgpu::ShaderStages, device: Arc<wgpu::Device>) -> Self
+    {
+        Self 
+        { 
+            device, 
+            visibility, 
+            free: Arc::new(Mutex::new(vec![])) 
+        }
+    }
+
+    /// Returns a buffer that can hold at least `capacity` elements, which is returned to the pool when dropped.
+    pub fn acquire(&self, capacity: u64) -> StagingBuffer<T>
+    {
+        let mut free = self.free.lock().unwrap();
+        let best_fit = free.iter()
+            .enumerate()
+            .filter(|(_, b)| b.capacity() >= capacity)
+            .min_by_key(|(_, b)| b.capacity())
+            .map(|(i, _)| i);
+
+        let buffer = match best_fit
+        {
+            Some(index) => free.swap_remove(index),
+            None => MappedBuffer::with_capacity(capacity, self.visibility, &self.device),
+        };
+
+        StagingBuffer 
+        { 
+            buffer: Some(buffer), 
+            pool: self.free.clone() 
+        }
+    }
+}
+
+pub struct StagingBuffer<T> where T : Byteable
+{
+    buffer: Option<MappedBuffer<T>>,
+    pool: Arc<Mutex<Vec<MappedBuffer<T>>>>
+}
+
+impl<T> Deref for StagingBuffer<T> where T : Byteable
+{
+    type Target = MappedBuffer<T>;
+
+    fn deref(&self) -> &Self::Target 
+    {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for StagingBuffer<T> where T : Byteable
+{
+    fn deref_mut(&mut self) -> &mut Self::Target 
+    {
+        self.buffer.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for StagingBuffer<T> where T : Byteable
+{
+    fn drop(&mut self) 
+    {
+        if let Some(buffer) = self.buffer.take()
+        {
+            self.pool.lock().unwrap().push(buffer);
+        }
+    }
+}
@@ -16,8 +16,9 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+/// Always in linear space, which is what the shaders and palette editor work in, the srgb surface encodes it on write
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Color 
 {
     pub r: f32,
@@ -43,6 +44,50 @@ impl Color
         Color { r, g, b, a }
     }
 
+    /// Decodes srgb bytes, like ones picked from an image editor, alpha is already linear
+    pub fn from_srgb_u8(r: u8, g: u8, b: u8, a: u8) -> Color
+    {
+        Color 
+        { 
+            r: Self::srgb_to_linear(r as f32 / 255.0), 
+            g: Self::srgb_to_linear(g as f32 / 255.0), 
+            b: Self::srgb_to_linear(b as f32 / 255.0), 
+            a: a as f32 / 255.0 
+        }
+    }
+
+    /// `0xRRGGBBAA`, decoded from srgb like `from_srgb_u8`
+    pub fn from_hex(hex: u32) -> Color
+    {
+        let [r, g, b, a] = hex.to_be_bytes();
+        Self::from_srgb_u8(r, g, b, a)
+    }
+
+    /// Blends every channel, in linear space
+    pub fn lerp(self, other: Color, t: f32) -> Color
+    {
+        Color 
+        { 
+            r: self.r + (other.r - self.r) * t, 
+            g: self.g + (other.g - self.g) * t, 
+            b: self.b + (other.b - self.b) * t, 
+            a: self.a + (other.a - self.a) * t 
+        }
+    }
+
+    /// The piecewise srgb transfer function, not the 2.2 gamma approximation
+    pub fn srgb_to_linear(value: f32) -> f32
+    {
+        if value <= 0.04045
+        {
+            value / 12.92
+        }
+        else 
+        {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
     pub fn rgb(self) -> Vec3<f32>
     {
         Vec3::new(self.r, self.g, self.b)
@@ -58,3 +103,195 @@ impl Color
         wgpu::Color { r: self.r as f64, g: self.g as f64, b: self.b as f64, a: self.a as f64 }
     }
 }
+
+impl From<Vec4<f32>> for Color
+{
+    fn from(value: Vec4<f32>) -> Self
+    {
+        Color::new(value.x, value.y, value.z, value.w)
+    }
+}
+
+impl From<Color> for Vec4<f32>
+{
+    fn from(value: Color) -> Self
+    {
+        value.rgba()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Plane
+{
+    pub normal: Vec3<f32>,
+    pub distance: f32
+}
+
+impl Plane
+{
+    pub fn new(normal: Vec3<f32>, distance: f32) -> Self
+    {
+        Self 
+        { 
+            normal, 
+            distance 
+        }
+    }
+
+    /// Builds a normalized plane from the `ax + by + cz + d = 0` coefficients
+    pub fn from_coefficients(coefficients: Vec4<f32>) -> Self
+    {
+        use cgmath::InnerSpace;
+        let normal = coefficients.truncate();
+        let length = normal.magnitude();
+        Self::new(normal / length, coefficients.w / length)
+    }
+
+    /// Positive on the side the normal points to
+    pub fn signed_distance(&self, point: Point3D<f32>) -> f32
+    {
+        use cgmath::{InnerSpace, EuclideanSpace};
+        self.normal.dot(point.to_vec()) + self.distance
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AABB
+{
+    pub min: Point3D<f32>,
+    pub max: Point3D<f32>
+}
+
+impl AABB
+{
+    pub fn new(min: Point3D<f32>, max: Point3D<f32>) -> Self
+    {
+        Self 
+        { 
+            min, 
+            max 
+        }
+    }
+
+    /// The smallest box containing both boxes
+    pub fn union(&self, other: &AABB) -> AABB
+    {
+        Self::new(
+            Point3D::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Point3D::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z))
+        )
+    }
+
+    /// Points on the faces of the box count as contained
+    pub fn contains_point(&self, point: Point3D<f32>) -> bool
+    {
+        point.x >= self.min.x && point.x <= self.max.x &&
+        point.y >= self.min.y && point.y <= self.max.y &&
+        point.z >= self.min.z && point.z <= self.max.z
+    }
+
+    pub fn center(&self) -> Point3D<f32>
+    {
+        use cgmath::EuclideanSpace;
+        self.min.midpoint(self.max)
+    }
+
+    pub fn surface_area(&self) -> f32
+    {
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// Conservative test, boxes near the frustum corners may pass even when they are not visible
+    pub fn intersects_frustum(&self, planes: &[Plane; 6]) -> bool
+    {
+        planes.iter().all(|plane| 
+        {
+            // the corner furthest along the plane normal
+            let positive = Point3D::new(
+                if plane.normal.x >= 0.0 { self.max.x } else { self.min.x },
+                if plane.normal.y >= 0.0 { self.max.y } else { self.min.y },
+                if plane.normal.z >= 0.0 { self.max.z } else { self.min.z },
+            );
+
+            plane.signed_distance(positive) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assert_close(a: f32, b: f32)
+    {
+        assert!((a - b).abs() < 1e-4, "{} is not close to {}", a, b);
+    }
+
+    #[test]
+    fn srgb_to_linear_matches_reference_values()
+    {
+        assert_close(Color::srgb_to_linear(0.0), 0.0);
+        assert_close(Color::srgb_to_linear(1.0), 1.0);
+        assert_close(Color::srgb_to_linear(0.5), 0.21404);
+        // on the linear segment
+        assert_close(Color::srgb_to_linear(0.04), 0.04 / 12.92);
+    }
+
+    #[test]
+    fn srgb_to_linear_is_continuous_at_the_threshold()
+    {
+        assert_close(Color::srgb_to_linear(0.04045), Color::srgb_to_linear(0.040451));
+    }
+
+    #[test]
+    fn from_hex_decodes_rgba_from_srgb()
+    {
+        let color = Color::from_hex(0xFF800040);
+        assert_close(color.r, 1.0);
+        assert_close(color.g, Color::srgb_to_linear(128.0 / 255.0));
+        assert_close(color.b, 0.0);
+        // alpha isn't srgb encoded
+        assert_close(color.a, 64.0 / 255.0);
+    }
+
+    #[test]
+    fn lerp_blends_every_channel()
+    {
+        let a = Color::new(0.0, 0.2, 1.0, 0.0);
+        let b = Color::new(1.0, 0.4, 0.0, 1.0);
+
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+
+        let half = a.lerp(b, 0.5);
+        assert_close(half.r, 0.5);
+        assert_close(half.g, 0.3);
+        assert_close(half.b, 0.5);
+        assert_close(half.a, 0.5);
+    }
+
+    #[test]
+    fn union_of_overlapping_boxes()
+    {
+        let a = AABB::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(2.0, 2.0, 2.0));
+        let b = AABB::new(Point3D::new(1.0, -1.0, 1.0), Point3D::new(3.0, 1.0, 4.0));
+
+        let union = a.union(&b);
+        assert_eq!(union.min, Point3D::new(0.0, -1.0, 0.0));
+        assert_eq!(union.max, Point3D::new(3.0, 2.0, 4.0));
+        assert_eq!(union.center(), Point3D::new(1.5, 0.5, 2.0));
+        assert_close(union.surface_area(), 2.0 * (3.0 * 3.0 + 3.0 * 4.0 + 4.0 * 3.0));
+    }
+
+    #[test]
+    fn point_on_a_face_is_contained()
+    {
+        let aabb = AABB::new(Point3D::new(0.0, 0.0, 0.0), Point3D::new(1.0, 1.0, 1.0));
+
+        assert!(aabb.contains_point(Point3D::new(1.0, 0.5, 0.5)));
+        assert!(aabb.contains_point(Point3D::new(0.5, 0.0, 0.5)));
+        assert!(!aabb.contains_point(Point3D::new(1.001, 0.5, 0.5)));
+    }
+}
@@ -2,17 +2,22 @@ pub mod renderer;
 pub mod debug_rendering;
 pub mod mesh;
 pub mod gui;
+pub mod frame_timer;
+pub mod hot_shader;
+pub mod crosshair;
 
 use std::sync::{Arc, Mutex};
 
-use crate::{math::*, voxel::{VoxelStorage, Voxel, terrain_renderer::TerrainRenderStage, terrain::VoxelTerrain}, camera::Camera};
+use crate::{math::*, voxel::{VoxelStorage, Voxel, terrain_renderer::{TerrainRenderStage, ProfilingInfo}, terrain::VoxelTerrain, voxel_rendering::{FogInfo, LightInfo}}, camera::Camera};
 use crate::gpu_utils::*;
 use wgpu::{VertexBufferLayout, BindGroupLayout};
 
-use self::{renderer::Renderer, debug_rendering::{DebugRenderStage, DebugObject}, mesh::{MeshRenderStage, Mesh, MeshInstance}, gui::{GuiRenderer, GuiRendererDescriptor}};
+use self::{renderer::Renderer, debug_rendering::{DebugRenderStage, DebugObject}, mesh::{MeshRenderStage, Mesh, MeshInstance}, gui::{GuiRenderer, GuiRendererDescriptor}, frame_timer::FrameTimer, crosshair::CrosshairStage};
 
 pub use crate::rendering::renderer::*;
 
+pub const DEFAULT_PALETTE_SAVE_PATH: &str = "voxel_palette.yaml";
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct ModelUniform
@@ -37,6 +42,32 @@ impl ModelUniform
 unsafe impl bytemuck::Pod for ModelUniform {}
 unsafe impl bytemuck::Zeroable for ModelUniform {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthConfig
+{
+    pub write: bool,
+    pub compare: wgpu::CompareFunction
+}
+
+impl DepthConfig
+{
+    /// Regular depth tested geometry
+    pub const DEFAULT: DepthConfig = DepthConfig { write: true, compare: wgpu::CompareFunction::Less };
+    /// Draws over everything without affecting the depth buffer, for debug overlays
+    pub const OVERLAY: DepthConfig = DepthConfig { write: false, compare: wgpu::CompareFunction::Always };
+
+    pub fn to_depth_stencil_state(self) -> wgpu::DepthStencilState
+    {
+        wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: self.write,
+            depth_compare: self.compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+}
+
 pub struct RenderPipelineInfo<'a>
 {
     pub shader: &'a wgpu::ShaderModule,
@@ -47,15 +78,46 @@ pub struct RenderPipelineInfo<'a>
     pub vertex_buffers: &'a [&'a VertexBufferLayout<'a>],
     pub bind_groups: &'a [&'a BindGroupLayout],
 
+    /// Requires `wgpu::Features::PUSH_CONSTANTS`, and the total size must fit in the device's `max_push_constant_size` (often 128 bytes).
+    /// Use `&[]` for pipelines without push constants.
+    pub push_constant_ranges: &'a [wgpu::PushConstantRange],
+
+    /// `Some(wgpu::BlendState::REPLACE)` for opaque geometry, `Some(wgpu::BlendState::ALPHA_BLENDING)` for transparent geometry
+    pub blend: Option<wgpu::BlendState>,
+
+    /// `None` builds a pipeline without a depth attachment, which must then be drawn in a pass without a depth texture
+    pub depth: Option<DepthConfig>,
+
+    /// Anything other than `Fill` needs the matching device feature, and falls back to `Fill` without it
+    pub polygon_mode: wgpu::PolygonMode,
+
+    /// Has to match the sample count of the textures the pipeline draws into, 1 for no MSAA
+    pub sample_count: u32,
+
     pub label: Option<&'a str>
 }
 
 pub fn construct_render_pipeline(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, info: &RenderPipelineInfo) -> wgpu::RenderPipeline
 {
+    let polygon_mode = match info.polygon_mode
+    {
+        wgpu::PolygonMode::Line if !device.features().contains(wgpu::Features::POLYGON_MODE_LINE) => 
+        {
+            eprintln!("Warning: POLYGON_MODE_LINE is not supported on this device, falling back to PolygonMode::Fill for {:?}", info.label);
+            wgpu::PolygonMode::Fill
+        },
+        wgpu::PolygonMode::Point if !device.features().contains(wgpu::Features::POLYGON_MODE_POINT) => 
+        {
+            eprintln!("Warning: POLYGON_MODE_POINT is not supported on this device, falling back to PolygonMode::Fill for {:?}", info.label);
+            wgpu::PolygonMode::Fill
+        },
+        mode => mode
+    };
+
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
         bind_group_layouts: &info.bind_groups,
-        push_constant_ranges: &[]
+        push_constant_ranges: info.push_constant_ranges
     });
 
     let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -74,7 +136,7 @@ pub fn construct_render_pipeline(device: &wgpu::Device, config: &wgpu::SurfaceCo
             entry_point: info.fs_main,
             targets: &[Some(wgpu::ColorTargetState {
                 format: config.format,
-                blend: Some(wgpu::BlendState::REPLACE),
+                blend: info.blend,
                 write_mask: wgpu::ColorWrites::ALL
             })],
         }),
@@ -85,20 +147,14 @@ pub fn construct_render_pipeline(device: &wgpu::Device, config: &wgpu::SurfaceCo
             front_face: wgpu::FrontFace::Ccw, 
             cull_mode: Some(wgpu::Face::Back), 
             unclipped_depth: false, 
-            polygon_mode: wgpu::PolygonMode::Fill, 
+            polygon_mode, 
             conservative: false 
         },
 
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less, // 1.
-            stencil: wgpu::StencilState::default(), // 2.
-            bias: wgpu::DepthBiasState::default(),
-        }),
+        depth_stencil: info.depth.map(|d| d.to_depth_stencil_state()),
     
         multisample: wgpu::MultisampleState { 
-            count: 1, 
+            count: info.sample_count, 
             mask: !0, 
             alpha_to_coverage_enabled: false 
         },
@@ -116,7 +172,13 @@ pub fn get_command_encoder(device: &wgpu::Device) -> wgpu::CommandEncoder
     })
 }
 
+/// Loads the color and depth already in the target
 pub fn get_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu::TextureView, depth_texture: Option<&'a Texture>) -> wgpu::RenderPass<'a>
+{
+    get_render_pass_with_ops(encoder, view, depth_texture, TargetOps::LOAD)
+}
+
+pub fn get_render_pass_with_ops<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu::TextureView, depth_texture: Option<&'a Texture>, ops: TargetOps) -> wgpu::RenderPass<'a>
 {
     let depth_stencil_attachment = match depth_texture
     {
@@ -125,7 +187,7 @@ pub fn get_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu
             Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &depth_texture.view,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
+                    load: ops.depth,
                     store: true,
                 }),
                 stencil_ops: None,
@@ -141,7 +203,7 @@ pub fn get_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu
             view: &view,
             resolve_target: None,
             ops: wgpu::Operations{
-                load: wgpu::LoadOp::Load,
+                load: ops.color,
                 store: true,
             }
         })],
@@ -159,12 +221,14 @@ pub struct RenderPassInfo<'a>
     pub depth_texture: Option<&'a Texture>,
     pub vertex_buffers: &'a [wgpu::BufferSlice<'a>],
     pub index_buffer: Option<wgpu::BufferSlice<'a>>,
-    pub index_format: wgpu::IndexFormat
+    pub index_format: wgpu::IndexFormat,
+    /// The `ops` given to `on_draw` for a stage's first pass, `TargetOps::LOAD` after that
+    pub ops: TargetOps
 }
 
 pub fn build_render_pass<'a>(info: RenderPassInfo<'a>) -> wgpu::RenderPass<'a>
 {
-    let mut render_pass = get_render_pass(info.command_encoder, info.view, info.depth_texture);
+    let mut render_pass = get_render_pass_with_ops(info.command_encoder, info.view, info.depth_texture, info.ops);
     render_pass.set_pipeline(info.render_pipeline);
     for i in 0..info.bind_groups.len()
     {
@@ -184,28 +248,51 @@ pub fn build_render_pass<'a>(info: RenderPassInfo<'a>) -> wgpu::RenderPass<'a>
     render_pass
 }
 
+/// The settings the Info window edits, copied out of the renderer each frame and applied after the gui ran
+#[derive(Debug, Clone, Copy)]
+struct DebugUiState
+{
+    wireframe: bool,
+    crosshair: bool,
+    clear_color: Color,
+    fog_enabled: bool,
+    fog_start: f32,
+    fog_density: f32,
+    render_scale: f32,
+    sample_count: u32,
+    present_mode: wgpu::PresentMode
+}
+
 pub struct GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Send + 'static
 {
     renderer: Renderer,
     debug_stage: DebugRenderStage,
     mesh_stage: MeshRenderStage,
     terrain_stage: TerrainRenderStage<TStorage>,
+    crosshair_stage: CrosshairStage,
     gui_stage: GuiRenderer,
-    delta_time: f32
+    frame_timer: FrameTimer,
+    /// From `WgpuState::supported_sample_counts`
+    sample_counts: Vec<u32>,
+    fog_enabled: bool,
+    /// Kept while the fog is off, so turning it back on restores it
+    fog_density: f32
 }
 
 impl<TStorage> GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Send + 'static
 {
-    pub fn new<T>(terrain: Arc<Mutex<VoxelTerrain<TStorage>>>, camera: Camera, device: Arc<wgpu::Device>, surface: Arc<wgpu::Surface>, queue: Arc<wgpu::Queue>, config: &wgpu::SurfaceConfiguration, event_loop: &winit::event_loop::EventLoop<T>, window: Arc<winit::window::Window>) -> Self
+    pub fn new<T>(terrain: Arc<Mutex<VoxelTerrain<TStorage>>>, camera: Camera, device: Arc<wgpu::Device>, surface: Arc<wgpu::Surface>, queue: Arc<wgpu::Queue>, config: &wgpu::SurfaceConfiguration, event_loop: &winit::event_loop::EventLoop<T>, window: Arc<winit::window::Window>, sample_counts: Vec<u32>) -> Self
         where T : 'static
     {
         let clear_color = Color::new(0.1, 0.2, 0.3, 1.0);
         let renderer = Renderer::new(device.clone(), surface, queue, config, clear_color);
 
-        let debug_stage = DebugRenderStage::new(device.clone(), config, camera.clone(), &[]);
+        let debug_stage = DebugRenderStage::new(device.clone(), config, camera.clone(), DepthConfig::DEFAULT, &[]);
         let mesh_stage = MeshRenderStage::new(Mesh::cube(Color::RED), &[MeshInstance::from_position([0.0, 2.0, 0.0].into())], camera.clone(), &device, config);
 
-        let terrain_stage = TerrainRenderStage::new(terrain, camera.clone(), device.clone(), config);
+        let mut terrain_stage = TerrainRenderStage::new(terrain, camera.clone(), device.clone(), config);
+
+        let crosshair_stage = CrosshairStage::new(&device, config, window.clone());
 
         let mut gui_stage = GuiRenderer::new(GuiRendererDescriptor {
             event_loop: &event_loop,
@@ -215,6 +302,11 @@ impl<TStorage> GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
         });
 
         gui_stage.load(gui::DEFAULT_SAVE_PATH);
+        terrain_stage.load_palette(DEFAULT_PALETTE_SAVE_PATH);
+
+        // fading into the clear color hides chunks popping in at the view distance
+        let fog_density = 0.02;
+        terrain_stage.set_fog(FogInfo::new(clear_color, 32.0, fog_density));
 
         Self 
         { 
@@ -222,8 +314,12 @@ impl<TStorage> GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
             debug_stage, 
             mesh_stage, 
             terrain_stage,
+            crosshair_stage,
             gui_stage,
-            delta_time: 0.0
+            frame_timer: FrameTimer::new(frame_timer::DEFAULT_FRAME_HISTORY),
+            sample_counts,
+            fog_enabled: true,
+            fog_density
         }
     }
 
@@ -232,7 +328,7 @@ impl<TStorage> GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
         self.debug_stage.update(debug_objects, camera.clone());
         self.mesh_stage.update(camera.clone());
         self.terrain_stage.update(camera.clone());
-        self.delta_time = delta_time;
+        self.frame_timer.record(delta_time);
     }
 
     pub fn handle_event<T>(&mut self, event: &winit::event::Event<T>) -> bool 
@@ -240,13 +336,56 @@ impl<TStorage> GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
         self.gui_stage.handle_event(event)
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError>
+    /// `present_mode` is edited by the gui, picking from `present_modes`. `ui` draws anything else into the same gui frame
+    pub fn render<F>(&mut self, present_mode: &mut wgpu::PresentMode, present_modes: &[wgpu::PresentMode], ui: F) -> Result<(), wgpu::SurfaceError>
+        where F : FnOnce(&egui::Context)
     {
         self.gui_stage.begin_frame();
-        self.gui_stage.draw_ui(|ctx| Self::basic_ui(ctx, self.delta_time));
+        let mut state = DebugUiState 
+        {
+            wireframe: self.terrain_stage.polygon_mode() == wgpu::PolygonMode::Line,
+            crosshair: self.crosshair_stage.is_enabled(),
+            clear_color: self.renderer.clear_color(),
+            fog_enabled: self.fog_enabled,
+            fog_start: self.terrain_stage.fog().start,
+            fog_density: self.fog_density,
+            render_scale: self.renderer.render_scale(),
+            sample_count: self.renderer.sample_count(),
+            present_mode: *present_mode
+        };
+        let mut voxel_colors = *self.terrain_stage.voxel_colors();
+        let voxel_names = self.terrain_stage.voxel_names();
+        self.gui_stage.draw_ui(|ctx| 
+        {
+            Self::basic_ui(ctx, &self.frame_timer, &mut state, &self.sample_counts, present_modes);
+            Self::palette_ui(ctx, voxel_names, &mut voxel_colors);
+            ui(ctx);
+        });
         self.gui_stage.end_frame();
 
-        self.renderer.render(&mut [&mut self.mesh_stage, &mut self.terrain_stage, &mut self.gui_stage])
+        self.terrain_stage.set_voxel_colors(voxel_colors);
+        if state.render_scale != self.renderer.render_scale()
+        {
+            self.renderer.set_render_scale(state.render_scale);
+        }
+
+        self.set_sample_count(state.sample_count);
+        *present_mode = state.present_mode;
+
+        self.terrain_stage.set_polygon_mode(if state.wireframe { wgpu::PolygonMode::Line } else { wgpu::PolygonMode::Fill });
+        self.crosshair_stage.set_enabled(state.crosshair);
+
+        self.fog_enabled = state.fog_enabled;
+        self.fog_density = state.fog_density;
+        let fog_color = self.terrain_stage.fog().color;
+        self.terrain_stage.set_fog(FogInfo::new(fog_color, state.fog_start, if state.fog_enabled { state.fog_density } else { 0.0 }));
+
+        if state.clear_color != self.renderer.clear_color()
+        {
+            self.set_sky(state.clear_color, self.terrain_stage.light());
+        }
+
+        self.renderer.render(&mut [&mut self.mesh_stage, &mut self.terrain_stage, &mut self.debug_stage], &mut [&mut self.crosshair_stage, &mut self.gui_stage])
     }
 
     pub fn resize(&mut self, config: &wgpu::SurfaceConfiguration)
@@ -254,12 +393,62 @@ impl<TStorage> GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
         self.renderer.resize(config);
     }
 
+    /// Sets the clear color and fades the terrain into it, `light` is the sun lighting the terrain
+    pub fn set_sky(&mut self, sky_color: Color, light: LightInfo)
+    {
+        self.renderer.set_clear_color(sky_color);
+        let fog = self.terrain_stage.fog();
+        self.terrain_stage.set_fog(FogInfo::new(sky_color, fog.start, fog.density));
+        self.terrain_stage.set_light(light);
+    }
+
+    pub fn sample_count(&self) -> u32 { self.renderer.sample_count() }
+
+    /// 1, 2, 4 or 8 samples per pixel, falling back to the largest supported count below `sample_count`.
+    /// The gui and crosshair are drawn after the resolve, so they are never multisampled
+    pub fn set_sample_count(&mut self, sample_count: u32)
+    {
+        let supported = self.sample_counts.iter()
+            .copied()
+            .filter(|count| *count <= sample_count)
+            .max()
+            .unwrap_or(1);
+
+        if supported != sample_count
+        {
+            eprintln!("Warning: {}x MSAA is not supported, falling back to {}x", sample_count, supported);
+        }
+
+        if supported == self.renderer.sample_count()
+        {
+            return;
+        }
+
+        self.renderer.set_sample_count(supported);
+        self.mesh_stage.set_sample_count(self.renderer.device(), supported);
+        self.terrain_stage.set_sample_count(supported);
+        self.debug_stage.set_sample_count(supported);
+    }
+
+    pub fn profiling_info(&self) -> ProfilingInfo { self.terrain_stage.profiling_info() }
+
+    pub fn set_profiling(&mut self, profiling: bool)
+    {
+        self.terrain_stage.set_profiling(profiling);
+    }
+
+    pub fn capture_frame(&mut self, path: &std::path::Path)
+    {
+        self.renderer.capture_frame(path);
+    }
+
     pub fn on_close(&mut self)
     {
         self.gui_stage.save(gui::DEFAULT_SAVE_PATH);
+        self.terrain_stage.save_palette(DEFAULT_PALETTE_SAVE_PATH);
     }
 
-    fn basic_ui(context: &egui::Context, delta_time: f32)
+    fn basic_ui(context: &egui::Context, frame_timer: &FrameTimer, state: &mut DebugUiState, sample_counts: &[u32], present_modes: &[wgpu::PresentMode])
     {
         egui::Window::new("Info")
             .vscroll(true)
@@ -268,7 +457,68 @@ impl<TStorage> GameRenderer<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
             .anchor(egui::Align2::RIGHT_TOP, egui::Vec2::default())
             .show(context, |ui| 
             {
-                ui.label(format!("Frame time: {:.2}ms", delta_time * 1000.0));
+                frame_timer.ui(ui);
+                ui.checkbox(&mut state.wireframe, "Wireframe");
+                ui.checkbox(&mut state.crosshair, "Crosshair");
+
+                // the day cycle sets this every frame while it's running
+                ui.horizontal(|ui|
+                {
+                    let clear_color = &mut state.clear_color;
+                    let mut rgb = [clear_color.r, clear_color.g, clear_color.b];
+                    ui.color_edit_button_rgb(&mut rgb);
+                    ui.label("Clear color");
+                    *clear_color = Color::new(rgb[0], rgb[1], rgb[2], clear_color.a);
+                });
+
+                // the fog always takes the clear color
+                ui.checkbox(&mut state.fog_enabled, "Fog");
+                ui.add_enabled(state.fog_enabled, egui::Slider::new(&mut state.fog_start, 0.0..=256.0).text("Fog start"));
+                ui.add_enabled(state.fog_enabled, egui::Slider::new(&mut state.fog_density, 0.001..=0.2).logarithmic(true).text("Fog density"));
+
+                ui.add(egui::Slider::new(&mut state.render_scale, 0.25..=2.0).text("Render scale"));
+
+                egui::ComboBox::from_label("MSAA")
+                    .selected_text(format!("{}x", state.sample_count))
+                    .show_ui(ui, |ui| 
+                    {
+                        for count in sample_counts
+                        {
+                            ui.selectable_value(&mut state.sample_count, *count, format!("{}x", count));
+                        }
+                    });
+
+                egui::ComboBox::from_label("Present mode")
+                    .selected_text(format!("{:?}", state.present_mode))
+                    .show_ui(ui, |ui| 
+                    {
+                        for mode in present_modes
+                        {
+                            ui.selectable_value(&mut state.present_mode, *mode, format!("{:?}", mode));
+                        }
+                    });
+            });
+    }
+
+    fn palette_ui(context: &egui::Context, voxel_names: &[&str], voxel_colors: &mut [Color])
+    {
+        egui::Window::new("Palette")
+            .resizable(false)
+            .default_open(false)
+            .show(context, |ui| 
+            {
+                egui::Grid::new("Palette Grid").show(ui, |ui| 
+                {
+                    for (name, color) in voxel_names.iter().zip(voxel_colors.iter_mut())
+                    {
+                        let mut rgba = [color.r, color.g, color.b, color.a];
+                        ui.label(*name);
+                        ui.color_edit_button_rgba_unmultiplied(&mut rgba);
+                        ui.end_row();
+
+                        *color = Color::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+                    }
+                });
             });
     }
 }
\ No newline at end of file
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use crate::math::Color;
+use crate::gpu_utils::{BindGroup, Uniform, Texture};
+
+use super::{construct_render_pipeline, RenderPipelineInfo, get_command_encoder, get_render_pass_with_ops, RenderStage, TargetOps, apply_target_ops};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CrosshairInfo
+{
+    color: Color,
+    arm: [f32; 2],
+    thickness: [f32; 2]
+}
+
+unsafe impl bytemuck::Pod for CrosshairInfo {}
+unsafe impl bytemuck::Zeroable for CrosshairInfo {}
+
+/// A cross at the center of the screen, drawn as an overlay stage so it isn't affected by the render scale
+pub struct CrosshairStage
+{
+    window: Arc<winit::window::Window>,
+    enabled: bool,
+    color: Color,
+    /// In logical pixels, scaled by the window's scale factor when drawn
+    arm_length: f32,
+    thickness: f32,
+
+    uniform: Uniform<CrosshairInfo>,
+    bind_group: BindGroup,
+    render_pipeline: wgpu::RenderPipeline
+}
+
+impl CrosshairStage
+{
+    pub const DEFAULT_ARM_LENGTH: f32 = 8.0;
+    pub const DEFAULT_THICKNESS: f32 = 2.0;
+
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, window: Arc<winit::window::Window>) -> Self
+    {
+        let uniform = Uniform::<CrosshairInfo>::new_empty(wgpu::ShaderStages::VERTEX_FRAGMENT, device);
+        let bind_group = BindGroup::new(&[&uniform], device);
+
+        let shader = &device.create_shader_module(wgpu::include_wgsl!("../shaders/crosshair_shader.wgsl"));
+        let render_pipeline = construct_render_pipeline(device, config, &RenderPipelineInfo {
+            shader,
+            vs_main: "vs_main",
+            fs_main: "fs_main",
+            vertex_buffers: &[],
+            bind_groups: &[bind_group.layout()],
+            push_constant_ranges: &[],
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            depth: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            sample_count: 1,
+            label: Some("Crosshair Render Pipeline")
+        });
+
+        Self 
+        { 
+            window, 
+            enabled: true, 
+            color: Color::new(1.0, 1.0, 1.0, 0.8), 
+            arm_length: Self::DEFAULT_ARM_LENGTH, 
+            thickness: Self::DEFAULT_THICKNESS, 
+            uniform, 
+            bind_group, 
+            render_pipeline 
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool { self.enabled }
+    pub fn set_enabled(&mut self, enabled: bool) { self.enabled = enabled; }
+
+    pub fn color(&self) -> Color { self.color }
+    pub fn set_color(&mut self, color: Color) { self.color = color; }
+}
+
+impl RenderStage for CrosshairStage
+{
+    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, _depth_texture: &Texture, ops: TargetOps) 
+    {
+        let size = self.window.inner_size();
+        if !self.enabled || size.width == 0 || size.height == 0
+        {
+            apply_target_ops(device, queue, view, None, ops);
+            return;
+        }
+
+        // clip space is 2 units across the screen
+        let scale = self.window.scale_factor() as f32;
+        let to_clip = |pixels: f32| [pixels * scale * 2.0 / size.width as f32, pixels * scale * 2.0 / size.height as f32];
+        self.uniform.enqueue_write(CrosshairInfo 
+        { 
+            color: self.color, 
+            arm: to_clip(self.arm_length), 
+            thickness: to_clip(self.thickness * 0.5) 
+        }, queue);
+
+        let mut encoder = get_command_encoder(device);
+        let mut render_pass = get_render_pass_with_ops(&mut encoder, view, None, ops);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, self.bind_group.bind_group(), &[]);
+        render_pass.draw(0..12, 0..1);
+        drop(render_pass);
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
@@ -1,13 +1,14 @@
 use std::sync::Arc;
 use std::cell::RefCell;
 
-use cgmath::{Zero, ElementWise};
+use cgmath::{Zero, ElementWise, EuclideanSpace};
 use wgpu::util::DeviceExt;
 
 use crate::gpu_utils::bind_group::{Uniform, BindGroup};
-use super::RenderStage;
+use super::{RenderStage, DepthConfig, TargetOps, get_command_encoder, get_render_pass_with_ops, apply_target_ops};
+use super::hot_shader::HotShader;
 use crate::camera::{Camera, CameraUniform};
-use crate::math::{Vec3, Color};
+use crate::math::{Vec3, Color, AABB};
 use crate::gpu_utils::texture::Texture;
 
 #[derive(Debug, Clone, Copy)]
@@ -158,6 +159,12 @@ pub enum DebugObject
 
 impl DebugObject
 {
+    /// The 12 edges of the box
+    pub fn from_aabb(aabb: &AABB, color: Color) -> Self
+    {
+        Self::Cube(DebugCube::new(aabb.min.to_vec(), aabb.max - aabb.min, color))
+    }
+
     fn append_vertices(&self, vec: &mut Vec<DebugLineVertex>)
     {
         match self 
@@ -204,6 +211,10 @@ pub struct DebugRenderStage
     device: Arc<wgpu::Device>,
 
     render_pipeline: wgpu::RenderPipeline,
+    shader: HotShader,
+    config: wgpu::SurfaceConfiguration,
+    depth: DepthConfig,
+    sample_count: u32,
 
     camera_uniform: RefCell<Uniform<CameraUniform>>,
     bind_group: BindGroup,
@@ -216,12 +227,14 @@ pub struct DebugRenderStage
 
 impl DebugRenderStage
 {
-    pub fn new(device: Arc<wgpu::Device>, config: &wgpu::SurfaceConfiguration, default_camera: Camera, debug_objects: &[DebugObject]) -> Self
+    /// Use `DepthConfig::OVERLAY` to draw the debug objects on top of all other geometry
+    pub fn new(device: Arc<wgpu::Device>, config: &wgpu::SurfaceConfiguration, default_camera: Camera, depth: DepthConfig, debug_objects: &[DebugObject]) -> Self
     {
         let camera_uniform = Uniform::<CameraUniform>::new_empty(wgpu::ShaderStages::VERTEX, &device);
         let bind_group = BindGroup::new(&[&camera_uniform], &device);
 
-        let render_pipeline = Self::gen_render_pipeline(&device, config, &bind_group);
+        let shader = crate::hot_shader!("src/shaders/debug_shader.wgsl");
+        let render_pipeline = Self::gen_render_pipeline(&device, config, &shader.create_module(&device), &bind_group, depth, 1);
 
         let (vertex_buffer, vertex_count) = Self::get_vertex_buffer(&device, debug_objects);
 
@@ -229,6 +242,10 @@ impl DebugRenderStage
         { 
             device: device.clone(), 
             render_pipeline, 
+            shader,
+            config: config.clone(),
+            depth,
+            sample_count: 1,
             camera_uniform: RefCell::new(camera_uniform),
             bind_group, 
             camera: default_camera, 
@@ -246,6 +263,20 @@ impl DebugRenderStage
         self.camera = camera;
     }
 
+    pub fn sample_count(&self) -> u32 { self.sample_count }
+
+    /// Rebuilds the render pipeline, has to match the sample count of the renderer's targets
+    pub fn set_sample_count(&mut self, sample_count: u32)
+    {
+        if self.sample_count == sample_count
+        {
+            return;
+        }
+
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::gen_render_pipeline(&self.device, &self.config, &self.shader.create_module(&self.device), &self.bind_group, self.depth, sample_count);
+    }
+
     fn get_vertex_buffer(device: &wgpu::Device, debug_objects: &[DebugObject]) -> (wgpu::Buffer, u32)
     {
         let mut vertices = vec![];
@@ -264,9 +295,8 @@ impl DebugRenderStage
         (buffer, vertices.len() as u32)
     }
 
-    fn gen_render_pipeline(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, camera_bind_group: &BindGroup) -> wgpu::RenderPipeline
+    fn gen_render_pipeline(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, shader: &wgpu::ShaderModule, camera_bind_group: &BindGroup, depth: DepthConfig, sample_count: u32) -> wgpu::RenderPipeline
     {
-        let shader = device.create_shader_module(wgpu::include_wgsl!("../shaders/debug_shader.wgsl"));
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Debug Render Pipeline Layout"),
             bind_group_layouts: &[&camera_bind_group.layout()],
@@ -277,13 +307,13 @@ impl DebugRenderStage
             label: Some("Debug Render Pipeline"),
             layout: Some(&render_pipeline_layout),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: "vs_main",
                 buffers: &[DebugLineVertex::desc()]
             },
             
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
                     format: config.format,
@@ -302,16 +332,10 @@ impl DebugRenderStage
                 conservative: false 
             },
 
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less, // 1.
-                stencil: wgpu::StencilState::default(), // 2.
-                bias: wgpu::DepthBiasState::default(),
-            }),
+            depth_stencil: Some(depth.to_depth_stencil_state()),
         
             multisample: wgpu::MultisampleState { 
-                count: 1, 
+                count: sample_count, 
                 mask: !0, 
                 alpha_to_coverage_enabled: false 
             },
@@ -324,9 +348,33 @@ impl DebugRenderStage
 
 impl RenderStage for DebugRenderStage
 {
-    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &Texture) 
+    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &Texture, ops: TargetOps) 
     {
-        todo!()
+        let (config, bind_group, depth, sample_count) = (&self.config, &self.bind_group, self.depth, self.sample_count);
+        if let Some(render_pipeline) = self.shader.reload(device, |shader| Self::gen_render_pipeline(device, config, shader, bind_group, depth, sample_count))
+        {
+            self.render_pipeline = render_pipeline;
+        }
+
+        if self.vertex_count == 0
+        {
+            apply_target_ops(device, queue, view, Some(depth_texture), ops);
+            return;
+        }
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&self.camera);
+        self.camera_uniform.borrow_mut().enqueue_write(camera_uniform, queue);
+
+        let mut encoder = get_command_encoder(device);
+        let mut render_pass = get_render_pass_with_ops(&mut encoder, view, Some(depth_texture), ops);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, self.bind_group.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+        drop(render_pass);
+
+        queue.submit(std::iter::once(encoder.finish()));
     }
 }
 
@@ -375,4 +423,29 @@ impl RenderStage for DebugRenderStage
 //         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 //         render_pass.draw(0..self.vertex_count, 0..1);
 //     }
-// }
\ No newline at end of file
+// }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::math::Point3D;
+
+    #[test]
+    fn aabb_has_two_vertices_per_edge()
+    {
+        let aabb = AABB::new(Point3D::new(-1.0, 2.0, 3.0), Point3D::new(1.0, 4.0, 7.0));
+        let mut vertices = vec![];
+        DebugObject::from_aabb(&aabb, Color::GREEN).append_vertices(&mut vertices);
+
+        assert_eq!(vertices.len(), 24);
+
+        // every edge ends on a corner, and each corner is shared by three edges
+        for vertex in &vertices
+        {
+            let p = vertex.position;
+            assert!([-1.0, 1.0].contains(&p.x) && [2.0, 4.0].contains(&p.y) && [3.0, 7.0].contains(&p.z), "{:?}", p);
+            assert_eq!(vertices.iter().filter(|v| v.position == p).count(), 3);
+        }
+    }
+}
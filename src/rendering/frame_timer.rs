@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+
+use egui_plot::{Plot, Line, PlotPoints};
+
+pub const DEFAULT_FRAME_HISTORY: usize = 120;
+
+/// Keeps the last few frame times around for the debug gui
+#[derive(Debug, Clone)]
+pub struct FrameTimer
+{
+    frame_times: VecDeque<f32>,
+    capacity: usize
+}
+
+impl FrameTimer
+{
+    pub fn new(capacity: usize) -> Self
+    {
+        Self 
+        { 
+            frame_times: VecDeque::with_capacity(capacity), 
+            capacity 
+        }
+    }
+
+    pub fn record(&mut self, delta_time: f32)
+    {
+        if self.frame_times.len() == self.capacity
+        {
+            self.frame_times.pop_front();
+        }
+
+        self.frame_times.push_back(delta_time);
+    }
+
+    pub fn last(&self) -> f32 { self.frame_times.back().copied().unwrap_or(0.0) }
+    pub fn min(&self) -> f32 { self.frame_times.iter().copied().reduce(f32::min).unwrap_or(0.0) }
+    pub fn max(&self) -> f32 { self.frame_times.iter().copied().reduce(f32::max).unwrap_or(0.0) }
+
+    pub fn average(&self) -> f32 
+    { 
+        if self.frame_times.is_empty()
+        {
+            return 0.0;
+        }
+
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32 
+    }
+
+    /// Draws the frame time stats and a graph of the history, in milliseconds
+    pub fn ui(&self, ui: &mut egui::Ui)
+    {
+        ui.label(format!("Frame time: {:.2}ms ({:.0} fps)", self.last() * 1000.0, 1.0 / self.average().max(f32::EPSILON)));
+        ui.label(format!("Min: {:.2}ms  Avg: {:.2}ms  Max: {:.2}ms", self.min() * 1000.0, self.average() * 1000.0, self.max() * 1000.0));
+
+        let points: PlotPoints = self.frame_times
+            .iter()
+            .enumerate()
+            .map(|(i, t)| [i as f64, *t as f64 * 1000.0])
+            .collect();
+
+        Plot::new("Frame times")
+            .height(80.0)
+            .include_x(0.0)
+            .include_x(self.capacity as f64)
+            .include_y(0.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show_axes([false, true])
+            .show(ui, |plot_ui| plot_ui.line(Line::new(points)));
+    }
+}
@@ -3,9 +3,9 @@ use std::{sync::Arc, fs::File, io::{Write, Read}, error::Error};
 use egui::FullOutput;
 use winit::event_loop::EventLoop;
 use egui_winit::egui::{Context, RawInput};
-use crate::rendering::RenderStage;
+use crate::rendering::{RenderStage, TargetOps};
 
-use super::{get_command_encoder, get_render_pass};
+use super::{get_command_encoder, get_render_pass_with_ops};
 
 pub const DEFAULT_SAVE_PATH: &str = "gui_data.yaml";
 
@@ -71,19 +71,24 @@ impl GuiRenderer
             .expect(format!("Could not write to file {}", path).as_str());
     }
 
+    /// Keeps the default memory if the file is missing or can't be parsed, so a bad file never stops the game from starting
     pub fn load(&mut self, path: &str)
     {
-        if let Ok(mut file) = File::open(path)
-        {
-            let mut yaml = String::new();
-            file.read_to_string(&mut yaml).expect(format!("Could not read file {}", path).as_str());
+        let Ok(mut file) = File::open(path) else { return; };
 
-            let memory: egui::Memory = serde_yaml::from_str(&yaml)
-                .expect("Could not deserialize gui context memory");
+        let mut yaml = String::new();
+        if let Err(e) = file.read_to_string(&mut yaml)
+        {
+            eprintln!("Could not read file {}: {}", path, e);
+            return;
+        }
 
-            self.context.memory_mut(|m| {
+        match serde_yaml::from_str::<egui::Memory>(&yaml)
+        {
+            Ok(memory) => self.context.memory_mut(|m| {
                 *m = memory
-            });
+            }),
+            Err(e) => eprintln!("Could not deserialize gui context memory from {}, using the default: {}", path, e)
         }
     }
 
@@ -107,7 +112,7 @@ impl GuiRenderer
 
 impl RenderStage for GuiRenderer
 {
-    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, _depth_texture: &crate::gpu_utils::Texture) 
+    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, _depth_texture: &crate::gpu_utils::Texture, ops: TargetOps) 
     {
         let size = self.window.inner_size();
         let screen_descriptor = egui_wgpu::renderer::ScreenDescriptor
@@ -132,7 +137,7 @@ impl RenderStage for GuiRenderer
             self.renderer.free_texture(&texture_id);
         }
 
-        let mut render_pass = get_render_pass(&mut encoder, view, None);
+        let mut render_pass = get_render_pass_with_ops(&mut encoder, view, None, ops);
         self.renderer.render(&mut render_pass, &clipped_primitives, &screen_descriptor);
         drop(render_pass);
 
@@ -0,0 +1,117 @@
+#[cfg(debug_assertions)]
+use std::path::PathBuf;
+
+/// A wgsl shader embedded in the binary. In debug builds the file it came from is watched,
+/// so editing it rebuilds whatever uses it without restarting
+pub struct HotShader
+{
+    label: &'static str,
+    source: &'static str,
+
+    #[cfg(debug_assertions)]
+    path: PathBuf,
+    #[cfg(debug_assertions)]
+    modified: Option<std::time::SystemTime>
+}
+
+/// Takes the path relative to the crate root, like `hot_shader!("src/shaders/mesh_shader.wgsl")`
+#[macro_export]
+macro_rules! hot_shader 
+{
+    ($path:literal) => 
+    {
+        $crate::rendering::hot_shader::HotShader::new(
+            $path,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/", $path),
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path))
+        )
+    };
+}
+
+impl HotShader
+{
+    /// Use `hot_shader!` instead, which embeds the file
+    pub fn new(label: &'static str, path: &str, source: &'static str) -> Self
+    {
+        #[cfg(not(debug_assertions))]
+        let _ = path;
+
+        Self 
+        { 
+            label, 
+            source,
+
+            #[cfg(debug_assertions)]
+            path: PathBuf::from(path),
+            #[cfg(debug_assertions)]
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok()
+        }
+    }
+
+    /// Always the embedded source, so a broken file on disk can't stop the game from starting
+    pub fn create_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule
+    {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor 
+        { 
+            label: Some(self.label), 
+            source: wgpu::ShaderSource::Wgsl(self.source.into()) 
+        })
+    }
+
+    /// If the file has changed since it was last read, compiles it and passes the module to `build`.
+    /// Compile and validation errors are printed and give `None`, so the caller keeps what it had.
+    /// Always `None` in release builds
+    pub fn reload<T, F>(&mut self, device: &wgpu::Device, build: F) -> Option<T>
+        where F : FnOnce(&wgpu::ShaderModule) -> T
+    {
+        #[cfg(debug_assertions)]
+        {
+            // polled instead of watched, a stat per shader per frame is cheap and needs no watcher thread
+            let modified = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+            if modified.is_none() || modified == self.modified
+            {
+                return None;
+            }
+
+            self.modified = modified;
+            let source = match std::fs::read_to_string(&self.path)
+            {
+                Ok(source) => source,
+                Err(e) => 
+                {
+                    eprintln!("Failed to read shader {}: {}", self.path.display(), e);
+                    return None;
+                }
+            };
+
+            // anything wrong with the shader is reported here instead of panicking in the default error handler
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor 
+            { 
+                label: Some(self.label), 
+                source: wgpu::ShaderSource::Wgsl(source.into()) 
+            });
+            let built = build(&module);
+
+            match pollster::block_on(device.pop_error_scope())
+            {
+                Some(error) => 
+                {
+                    eprintln!("Failed to reload shader {}:\n{}", self.path.display(), error);
+                    None
+                },
+                None => 
+                {
+                    println!("Reloaded shader {}", self.path.display());
+                    Some(built)
+                }
+            }
+        }
+
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = (device, build);
+            None
+        }
+    }
+}
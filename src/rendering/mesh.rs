@@ -2,10 +2,11 @@ use std::cell::RefCell;
 
 use crate::camera::{Camera, CameraUniform};
 use crate::math::*;
-use crate::rendering::RenderStage;
+use crate::rendering::{RenderStage, TargetOps};
 
 use crate::gpu_utils::{BindGroup, Uniform, VertexBuffer, VertexData, IndexBuffer, Texture};
-use super::{construct_render_pipeline, RenderPipelineInfo, get_command_encoder, RenderPassInfo, build_render_pass};
+use super::hot_shader::HotShader;
+use super::{construct_render_pipeline, RenderPipelineInfo, get_command_encoder, RenderPassInfo, build_render_pass, DepthConfig};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -126,6 +127,32 @@ impl MeshInstance
             data_raw: mat.into() 
         }
     }
+
+    pub fn position(&self) -> Vec3<f32>
+    {
+        let [x, y, z, _] = self.data_raw[3];
+        Vec3::new(x, y, z)
+    }
+
+    /// Only moves the instance, any rotation or scale in the transform is kept
+    pub fn set_position(&mut self, position: Vec3<f32>)
+    {
+        self.data_raw[3] = [position.x, position.y, position.z, 1.0];
+    }
+
+    pub fn translate(&mut self, delta: Vec3<f32>)
+    {
+        self.set_position(self.position() + delta);
+    }
+}
+
+/// Refers to an instance added with `MeshRenderStage::add_instance`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstanceHandle(usize);
+
+impl InstanceHandle
+{
+    pub fn index(&self) -> usize { self.0 }
 }
 
 unsafe impl bytemuck::Pod for MeshInstance {}
@@ -151,7 +178,11 @@ pub struct MeshRenderStage
     vertex_buffer: VertexBuffer<Vertex>,
     index_buffer: IndexBuffer,
     instance_buffer: VertexBuffer<MeshInstance>,
+    instances: Vec<MeshInstance>,
     render_pipeline: wgpu::RenderPipeline,
+    shader: HotShader,
+    config: wgpu::SurfaceConfiguration,
+    sample_count: u32,
 
     camera_uniform: RefCell<Uniform<CameraUniform>>,
     camera_bind_group: BindGroup,
@@ -172,23 +203,19 @@ impl MeshRenderStage
 
         let camera_bind_group = BindGroup::new(&[&camera_uniform], device);
 
-        let shader = &device.create_shader_module(wgpu::include_wgsl!("../shaders/mesh_shader.wgsl"));
-        let render_pipeline = construct_render_pipeline(device, config, &RenderPipelineInfo 
-        { 
-            shader,
-            vs_main: "vs_main",
-            fs_main: "fs_main",
-            vertex_buffers: &[&Vertex::desc(), &MeshInstance::desc()],
-            bind_groups: &[camera_bind_group.layout()], 
-            label: Some("Mesh render pipeline")
-        });
+        let shader = crate::hot_shader!("src/shaders/mesh_shader.wgsl");
+        let render_pipeline = Self::create_render_pipeline(device, config, &shader.create_module(device), &camera_bind_group, 1);
 
         Self 
         { 
             vertex_buffer, 
             index_buffer, 
             instance_buffer, 
+            instances: transforms.to_vec(),
             render_pipeline,
+            shader,
+            config: config.clone(),
+            sample_count: 1,
             camera_uniform: RefCell::new(camera_uniform), 
             camera_bind_group, 
             camera 
@@ -199,12 +226,70 @@ impl MeshRenderStage
     {
         self.camera = camera
     }
+
+    pub fn instances(&self) -> &[MeshInstance] { &self.instances }
+
+    /// Draws another copy of the mesh at `instance`, sharing the same vertex and index buffers.
+    /// Returns the handle to pass to `update_instance`
+    pub fn add_instance(&mut self, instance: MeshInstance, device: &wgpu::Device, queue: &wgpu::Queue) -> InstanceHandle
+    {
+        self.instances.push(instance);
+        self.instance_buffer.enqueue_write_or_grow(&self.instances, device, queue);
+        InstanceHandle(self.instances.len() - 1)
+    }
+
+    /// Re-uploads only the one instance, so moving a few meshes doesn't rewrite the whole instance buffer
+    pub fn update_instance(&mut self, handle: InstanceHandle, instance: MeshInstance, queue: &wgpu::Queue)
+    {
+        let index = handle.index();
+        assert!(index < self.instances.len(), "Instance index {} is out of range of {} instances", index, self.instances.len());
+        self.instances[index] = instance;
+        self.instance_buffer.enqueue_write_at(index as u64, &[instance], queue);
+    }
+
+    pub fn sample_count(&self) -> u32 { self.sample_count }
+
+    /// Rebuilds the render pipeline, has to match the sample count of the renderer's targets
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32)
+    {
+        if self.sample_count == sample_count
+        {
+            return;
+        }
+
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::create_render_pipeline(device, &self.config, &self.shader.create_module(device), &self.camera_bind_group, sample_count);
+    }
+
+    fn create_render_pipeline(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, shader: &wgpu::ShaderModule, camera_bind_group: &BindGroup, sample_count: u32) -> wgpu::RenderPipeline
+    {
+        construct_render_pipeline(device, config, &RenderPipelineInfo 
+        { 
+            shader,
+            vs_main: "vs_main",
+            fs_main: "fs_main",
+            vertex_buffers: &[&Vertex::desc(), &MeshInstance::desc()],
+            bind_groups: &[camera_bind_group.layout()], 
+            push_constant_ranges: &[],
+            blend: Some(wgpu::BlendState::REPLACE),
+            depth: Some(DepthConfig::DEFAULT),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            sample_count,
+            label: Some("Mesh render pipeline")
+        })
+    }
 }
 
 impl RenderStage for MeshRenderStage
 {
-    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &Texture) 
+    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &Texture, ops: TargetOps) 
     {
+        let (config, camera_bind_group, sample_count) = (&self.config, &self.camera_bind_group, self.sample_count);
+        if let Some(render_pipeline) = self.shader.reload(device, |shader| Self::create_render_pipeline(device, config, shader, camera_bind_group, sample_count))
+        {
+            self.render_pipeline = render_pipeline;
+        }
+
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&self.camera);
         self.camera_uniform.borrow_mut().enqueue_write(camera_uniform, queue);
@@ -220,13 +305,87 @@ impl RenderStage for MeshRenderStage
             vertex_buffers: &[self.vertex_buffer.slice_all(), self.instance_buffer.slice_all()],
             index_buffer: Some(self.index_buffer.slice(..)),
             index_format: wgpu::IndexFormat::Uint32,
+            ops
         };
 
         let mut render_pass = build_render_pass(info);
-        render_pass.draw_indexed(0..(self.index_buffer.capacity() as u32), 0, 0..(self.instance_buffer.capacity() as u32));
+        render_pass.draw_indexed(0..(self.index_buffer.capacity() as u32), 0, 0..(self.instance_buffer.length() as u32));
         drop(render_pass);
 
         queue.submit(std::iter::once(command_encoder.finish()));
     }
 }
 
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use cgmath::{Deg, Matrix3};
+    use crate::gpu_utils::{WgpuState, WgpuOptions, WgpuInitError};
+    use crate::rendering::renderer::RenderTarget;
+
+    const SIZE: u32 = 32;
+
+    #[test]
+    fn set_position_keeps_rotation_and_scale()
+    {
+        let transform = Mat4x4::from_translation(Vec3::new(1.0, 2.0, 3.0)) 
+            * Mat4x4::from(Matrix3::from_angle_y(Deg(30.0))) 
+            * Mat4x4::from_nonuniform_scale(2.0, 3.0, 4.0);
+        let mut instance = MeshInstance { data_raw: transform.into() };
+
+        instance.set_position(Vec3::new(-5.0, 0.5, 7.0));
+        assert_eq!(instance.position(), Vec3::new(-5.0, 0.5, 7.0));
+        assert_eq!(instance.data_raw[..3], <[[f32; 4]; 4]>::from(transform)[..3]);
+
+        instance.translate(Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(instance.position(), Vec3::new(-4.0, 1.5, 8.0));
+        assert_eq!(instance.data_raw[..3], <[[f32; 4]; 4]>::from(transform)[..3]);
+    }
+
+    #[test]
+    fn every_instance_renders_at_its_position()
+    {
+        let state = match pollster::block_on(WgpuState::new_headless(Vec2::new(SIZE, SIZE), WgpuOptions::default()))
+        {
+            Ok(state) => state,
+            Err(WgpuInitError::NoAdapter { .. }) => 
+            {
+                eprintln!("No adapter available, skipping the mesh instance test");
+                return;
+            },
+            Err(e) => panic!("Could not create a headless device: {}", e)
+        };
+
+        let camera = Camera 
+        { 
+            eye: Point3D::new(0.0, 0.0, 5.0), 
+            target: Point3D::new(0.0, 0.0, 0.0), 
+            up: Vec3::unit_y(), 
+            aspect: 1.0, 
+            fov: 90.0, 
+            near: 0.1, 
+            far: 100.0 
+        };
+
+        // unit cubes centered on x = -2 and x = 2, with nothing in between
+        let mut stage = MeshRenderStage::new(Mesh::cube(Color::RED), &[MeshInstance::from_position(Vec3::new(-2.5, -0.5, -0.5))], camera.clone(), state.device(), state.surface_config());
+        stage.add_instance(MeshInstance::from_position(Vec3::new(1.5, -0.5, -0.5)), state.device(), state.queue());
+
+        let target = RenderTarget::new(state.device(), state.surface_config());
+        target.render(state.device(), state.queue(), Color::BLUE, &mut [&mut stage]);
+        let pixels = target.read_rgba(state.device(), state.queue()).unwrap();
+
+        let pixel_at = |point: Point3D<f32>| 
+        {
+            let screen = camera.world_to_screen(point, Vec2::new(SIZE as f32, SIZE as f32)).unwrap();
+            let i = (screen.y as usize * SIZE as usize + screen.x as usize) * 4;
+            [pixels[i], pixels[i + 1], pixels[i + 2]]
+        };
+
+        assert_eq!(pixel_at(Point3D::new(-2.0, 0.0, 0.5)), [255, 0, 0]);
+        assert_eq!(pixel_at(Point3D::new(2.0, 0.0, 0.5)), [255, 0, 0]);
+        assert_eq!(pixel_at(Point3D::new(0.0, 0.0, 0.5)), [0, 0, 255]);
+    }
+}
@@ -1,10 +1,207 @@
 use std::sync::Arc;
+use std::path::{Path, PathBuf};
 use crate::math::Color;
-use crate::gpu_utils::texture::Texture;
+use crate::gpu_utils::texture::{Texture, MipGenerator};
+use crate::gpu_utils::{MappedBuffer, BindGroup, TextureEntry, SamplerEntry};
+use crate::rendering::{construct_render_pipeline, RenderPipelineInfo, get_command_encoder, get_render_pass, get_render_pass_with_ops};
 
+/// What a stage's first render pass does with the color and depth already in the target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetOps
+{
+    pub color: wgpu::LoadOp<wgpu::Color>,
+    pub depth: wgpu::LoadOp<f32>
+}
+
+impl TargetOps
+{
+    /// Draws over whatever the earlier stages left
+    pub const LOAD: TargetOps = TargetOps { color: wgpu::LoadOp::Load, depth: wgpu::LoadOp::Load };
+
+    pub fn clear(color: Color) -> Self
+    {
+        Self 
+        { 
+            color: wgpu::LoadOp::Clear(color.to_wgpu()), 
+            depth: wgpu::LoadOp::Clear(1.0) 
+        }
+    }
+
+    pub fn clears(&self) -> bool
+    {
+        matches!(self.color, wgpu::LoadOp::Clear(_)) || matches!(self.depth, wgpu::LoadOp::Clear(_))
+    }
+
+    /// Anything left as `Load` is cleared instead, since there is nothing to load yet
+    fn first_in_target(self, clear_color: Color) -> Self
+    {
+        let clear = Self::clear(clear_color);
+        Self 
+        { 
+            color: if self.color == wgpu::LoadOp::Load { clear.color } else { self.color }, 
+            depth: if self.depth == wgpu::LoadOp::Load { clear.depth } else { self.depth } 
+        }
+    }
+}
+
+/// Stages draw in the order they are given to the renderer. To migrate a stage, begin its first render pass with the `ops` 
+/// passed to `on_draw`, through `RenderPassInfo::ops` or `get_render_pass_with_ops`, and `TargetOps::LOAD` for any passes after that.
+/// A stage that can draw nothing, like the terrain with every chunk culled, still has to call `apply_target_ops`
 pub trait RenderStage
 {
-    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &Texture);
+    /// What the stage needs done to the target before it draws, which is loading it by default.
+    /// The first stage in a target is always given a clear for anything it loads, so no separate clear pass is needed
+    fn target_ops(&self) -> TargetOps { TargetOps::LOAD }
+
+    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &Texture, ops: TargetOps);
+}
+
+/// Draws `stages` in order, the first one clearing the target to `clear_color`
+pub fn draw_stages(device: &wgpu::Device, queue: &wgpu::Queue, clear_color: Color, view: &wgpu::TextureView, depth_texture: &Texture, stages: &mut [&mut dyn RenderStage])
+{
+    if stages.is_empty()
+    {
+        apply_target_ops(device, queue, view, Some(depth_texture), TargetOps::clear(clear_color));
+        return;
+    }
+
+    for (i, stage) in stages.iter_mut().enumerate()
+    {
+        let ops = if i == 0 { stage.target_ops().first_in_target(clear_color) } else { stage.target_ops() };
+        stage.on_draw(device, queue, view, depth_texture, ops);
+    }
+}
+
+/// Runs an empty pass, so `ops` still happen for a stage that didn't begin any passes itself
+pub fn apply_target_ops(device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: Option<&Texture>, ops: TargetOps)
+{
+    if !ops.clears()
+    {
+        return;
+    }
+
+    let mut encoder = get_command_encoder(device);
+    drop(get_render_pass_with_ops(&mut encoder, view, depth_texture, ops));
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
+/// A color and depth target the stages draw into, which is then scaled onto the surface
+struct OffscreenTarget
+{
+    color_texture: Texture,
+    depth_texture: Texture,
+    blit_bind_group: BindGroup,
+    blit_pipeline: wgpu::RenderPipeline,
+    /// Only when the target is larger than the surface, so the blit can filter trilinearly
+    mip_generator: Option<MipGenerator>
+}
+
+impl OffscreenTarget
+{
+    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, render_scale: f32) -> Self
+    {
+        let width = ((config.width as f32 * render_scale) as u32).max(1);
+        let height = ((config.height as f32 * render_scale) as u32).max(1);
+
+        // same format as the surface, so the stage pipelines work with either target
+        let (color_texture, mip_generator) = if render_scale > 1.0
+        {
+            let texture = Texture::create_mipped_render_target(device, width, height, config.format, "offscreen_color_texture");
+            (texture, Some(MipGenerator::new(device, config.format)))
+        }
+        else 
+        {
+            (Texture::create_render_target(device, width, height, config.format, "offscreen_color_texture"), None)
+        };
+        let depth_texture = Texture::create_depth_texture_with_size(device, width, height, "offscreen_depth_texture");
+
+        // the color texture's own view is only the base mip level
+        let sample_view = color_texture.texture().create_view(&wgpu::TextureViewDescriptor::default());
+        let blit_bind_group = BindGroup::new(&[
+            &TextureEntry::new(&sample_view, wgpu::ShaderStages::FRAGMENT), 
+            &SamplerEntry::new(color_texture.sampler(), wgpu::ShaderStages::FRAGMENT)
+        ], device);
+
+        let shader = &device.create_shader_module(wgpu::include_wgsl!("../shaders/screen_shader.wgsl"));
+        let blit_pipeline = construct_render_pipeline(device, config, &RenderPipelineInfo {
+            shader,
+            vs_main: "vs_main",
+            fs_main: "fs_main",
+            vertex_buffers: &[],
+            bind_groups: &[blit_bind_group.layout()],
+            push_constant_ranges: &[],
+            blend: Some(wgpu::BlendState::REPLACE),
+            depth: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            sample_count: 1,
+            label: Some("Blit Render Pipeline")
+        });
+
+        Self 
+        { 
+            color_texture, 
+            depth_texture, 
+            blit_bind_group, 
+            blit_pipeline,
+            mip_generator
+        }
+    }
+
+    fn blit(&self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView)
+    {
+        if let Some(mip_generator) = &self.mip_generator
+        {
+            mip_generator.generate(&self.color_texture, device, queue);
+        }
+
+        let mut encoder = get_command_encoder(device);
+        let mut render_pass = get_render_pass(&mut encoder, view, None);
+        render_pass.set_pipeline(&self.blit_pipeline);
+        render_pass.set_bind_group(0, self.blit_bind_group.bind_group(), &[]);
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+/// Multisampled color and depth textures the stages draw into, which are resolved into the real target afterwards
+struct MsaaTarget
+{
+    color_texture: Texture,
+    depth_texture: Texture
+}
+
+impl MsaaTarget
+{
+    fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, sample_count: u32) -> Self
+    {
+        Self 
+        { 
+            color_texture: Texture::create_multisampled_render_target(device, width, height, format, sample_count, "msaa_color_texture"), 
+            depth_texture: Texture::create_depth_texture_with_samples(device, width, height, sample_count, "msaa_depth_texture") 
+        }
+    }
+
+    /// Averages the samples into `view`, which has to be the same size and format
+    fn resolve(&self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView)
+    {
+        let mut encoder = get_command_encoder(device);
+        drop(encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("MSAA Resolve Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.color_texture.view,
+                resolve_target: Some(view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: false,
+                }
+            })],
+            depth_stencil_attachment: None
+        }));
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
 }
 
 pub struct Renderer
@@ -12,8 +209,16 @@ pub struct Renderer
     device: Arc<wgpu::Device>,
     surface: Arc<wgpu::Surface>,
     queue: Arc<wgpu::Queue>,
+    config: wgpu::SurfaceConfiguration,
     depth_texture: Texture,
-    clear_color: Color
+    clear_color: Color,
+    pending_capture: Option<PathBuf>,
+
+    render_scale: f32,
+    offscreen_target: Option<OffscreenTarget>,
+
+    sample_count: u32,
+    msaa_target: Option<MsaaTarget>
 }
 
 impl Renderer
@@ -26,21 +231,129 @@ impl Renderer
             device, 
             surface, 
             queue, 
+            config: config.clone(),
             depth_texture,
-            clear_color
+            clear_color,
+            pending_capture: None,
+            render_scale: 1.0,
+            offscreen_target: None,
+            sample_count: 1,
+            msaa_target: None
         }
     }
 
-    pub fn render(&self, stages: &mut [&mut dyn RenderStage]) -> Result<(), wgpu::SurfaceError>
+    /// Renders the stages into an offscreen target `render_scale` times the size of the surface, which is then stretched over the surface
+    pub fn with_offscreen(device: Arc<wgpu::Device>, surface: Arc<wgpu::Surface>, queue: Arc<wgpu::Queue>, config: &wgpu::SurfaceConfiguration, clear_color: Color, render_scale: f32) -> Self
+    {
+        let mut renderer = Self::new(device, surface, queue, config, clear_color);
+        renderer.set_render_scale(render_scale);
+        renderer
+    }
+
+    pub fn device(&self) -> &Arc<wgpu::Device> { &self.device }
+    pub fn render_scale(&self) -> f32 { self.render_scale }
+    pub fn clear_color(&self) -> Color { self.clear_color }
+
+    /// Given to the first stage each frame, so it only shows where no stage draws, which is the sky behind the terrain.
+    /// The terrain fog fades to its own color, `GameRenderer::set_sky` changes both together
+    pub fn set_clear_color(&mut self, clear_color: Color)
+    {
+        self.clear_color = clear_color;
+    }
+
+    /// Switches to offscreen rendering if it isn't already being used
+    pub fn set_render_scale(&mut self, render_scale: f32)
+    {
+        assert!(render_scale > 0.0, "Render scale must be positive");
+        if self.offscreen_target.is_some() && self.render_scale == render_scale
+        {
+            return;
+        }
+
+        self.render_scale = render_scale;
+        self.offscreen_target = Some(OffscreenTarget::new(&self.device, &self.config, render_scale));
+        self.rebuild_msaa_target();
+    }
+
+    pub fn sample_count(&self) -> u32 { self.sample_count }
+
+    /// 1 turns MSAA off. Every stage passed to `render` has to be rebuilt with the same sample count, 
+    /// overlays always draw at 1 sample after the resolve. The count must be one of `WgpuState::supported_sample_counts`
+    pub fn set_sample_count(&mut self, sample_count: u32)
+    {
+        if self.sample_count == sample_count
+        {
+            return;
+        }
+
+        self.sample_count = sample_count;
+        self.rebuild_msaa_target();
+    }
+
+    /// Sized to whichever target the stages end up in
+    fn rebuild_msaa_target(&mut self)
+    {
+        if self.sample_count <= 1
+        {
+            self.msaa_target = None;
+            return;
+        }
+
+        let size = match &self.offscreen_target
+        {
+            Some(target) => target.color_texture.texture().size(),
+            None => self.depth_texture.texture().size()
+        };
+
+        self.msaa_target = Some(MsaaTarget::new(&self.device, size.width, size.height, self.config.format, self.sample_count));
+    }
+
+    /// Saves the next rendered frame to `path` as a png
+    pub fn capture_frame(&mut self, path: &Path)
+    {
+        self.pending_capture = Some(path.into());
+    }
+
+    /// `overlay_stages` are always drawn straight onto the surface at full resolution, after `stages`
+    pub fn render(&mut self, stages: &mut [&mut dyn RenderStage], overlay_stages: &mut [&mut dyn RenderStage]) -> Result<(), wgpu::SurfaceError>
     {
         let output = self.surface.get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        self.clear_color(self.clear_color, &view);
+        let (target_view, target_depth) = match &self.offscreen_target
+        {
+            Some(target) => (&target.color_texture.view, &target.depth_texture),
+            None => (&view, &self.depth_texture)
+        };
+
+        match &self.msaa_target
+        {
+            Some(msaa) =>
+            {
+                draw_stages(&self.device, &self.queue, self.clear_color, &msaa.color_texture.view, &msaa.depth_texture, stages);
+                msaa.resolve(&self.device, &self.queue, target_view);
+            },
+            None => draw_stages(&self.device, &self.queue, self.clear_color, target_view, target_depth, stages)
+        }
+
+        if let Some(target) = &self.offscreen_target
+        {
+            target.blit(&self.device, &self.queue, &view);
+        }
+
+        // the surface is already covered by the stages or the blit
+        for stage in overlay_stages.iter_mut()
+        {
+            let ops = stage.target_ops();
+            stage.on_draw(&self.device, &self.queue, &view, &self.depth_texture, ops);
+        }
 
-        for stage in stages.iter_mut()
+        if let Some(path) = self.pending_capture.take()
         {
-            stage.on_draw(&self.device, &self.queue, &view, &self.depth_texture);
+            if let Err(e) = self.save_texture(&output.texture, &path)
+            {
+                eprintln!("Failed to save screenshot to {}: {}", path.display(), e);
+            }
         }
 
         output.present();
@@ -50,37 +363,187 @@ impl Renderer
 
     pub fn resize(&mut self, config: &wgpu::SurfaceConfiguration)
     {
+        self.config = config.clone();
         self.depth_texture = Texture::create_depth_texture(&self.device, config, "depth_texture");
+
+        if self.offscreen_target.is_some()
+        {
+            self.offscreen_target = Some(OffscreenTarget::new(&self.device, config, self.render_scale));
+        }
+
+        self.rebuild_msaa_target();
+    }
+
+    fn save_texture(&self, texture: &wgpu::Texture, path: &Path) -> Result<(), String>
+    {
+        let pixels = read_texture_rgba(&self.device, &self.queue, texture)?;
+
+        // the surface bytes are already srgb encoded, which is what png expects
+        image::save_buffer(path, &pixels, texture.width(), texture.height(), image::ColorType::Rgba8).map_err(|e| e.to_string())
+    }
+}
+
+/// Color and depth textures to draw the stages into without a surface, e.g. with a headless `WgpuState`
+pub struct RenderTarget
+{
+    color_texture: Texture,
+    depth_texture: Texture
+}
+
+impl RenderTarget
+{
+    pub fn color_texture(&self) -> &Texture { &self.color_texture }
+    pub fn depth_texture(&self) -> &Texture { &self.depth_texture }
+
+    /// Same size and format as `config`, so pipelines built from it can draw here
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self
+    {
+        Self 
+        { 
+            color_texture: Texture::create_render_target(device, config.width, config.height, config.format, "render_target_color_texture"), 
+            depth_texture: Texture::create_depth_texture_with_size(device, config.width, config.height, "render_target_depth_texture") 
+        }
+    }
+
+    /// Clears the target, then draws the stages in order like `Renderer::render`
+    pub fn render(&self, device: &wgpu::Device, queue: &wgpu::Queue, clear_color: Color, stages: &mut [&mut dyn RenderStage])
+    {
+        draw_stages(device, queue, clear_color, &self.color_texture.view, &self.depth_texture, stages);
+    }
+
+    /// Tightly packed rgba rows, waits for the gpu to finish drawing
+    pub fn read_rgba(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<u8>, String>
+    {
+        read_texture_rgba(device, queue, self.color_texture.texture())
+    }
+}
+
+/// Copies an rgba or bgra texture back to the cpu as tightly packed rgba rows, with the alpha set to opaque
+fn read_texture_rgba(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Result<Vec<u8>, String>
+{
+    if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC)
+    {
+        return Err("the texture does not support copying from it".into());
     }
 
-    fn clear_color(&self, clear_color: Color, view: &wgpu::TextureView)
+    let swap_red_blue = match texture.format().remove_srgb_suffix()
+    {
+        wgpu::TextureFormat::Rgba8Unorm => false,
+        wgpu::TextureFormat::Bgra8Unorm => true,
+        format => return Err(format!("unsupported texture format {:?}", format))
+    };
+
+    let (width, height) = (texture.width(), texture.height());
+    let unpadded_bytes_per_row = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let bytes_per_row = (unpadded_bytes_per_row + align - 1) / align * align;
+
+    let mut buffer = MappedBuffer::<u8>::with_capacity(bytes_per_row as u64 * height as u64, wgpu::ShaderStages::NONE, device);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor 
     {
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor 
+        label: Some("Capture Encoder")
+    });
+
+    buffer.copy_from_texture(texture, bytes_per_row, &mut encoder);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let padded = buffer.read(device);
+
+    // the rows are padded out to the copy alignment, and the texture may be bgra
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    for row in padded.chunks(bytes_per_row as usize)
+    {
+        for pixel in row[..unpadded_bytes_per_row as usize].chunks(4)
         {
-            label: Some("Render Encoder")
-        });
+            let (r, b) = if swap_red_blue { (pixel[2], pixel[0]) } else { (pixel[0], pixel[2]) };
+            pixels.extend_from_slice(&[r, pixel[1], b, 255]);
+        }
+    }
 
-        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations{
-                    load: wgpu::LoadOp::Clear(clear_color.to_wgpu()),
-                    store: true,
-                }
-            })],
+    Ok(pixels)
+}
 
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: true,
-                }),
-                stencil_ops: None,
-            }),
-        });
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::gpu_utils::{WgpuState, WgpuOptions, WgpuInitError};
+    use crate::math::Vec2;
+
+    const SIZE: u32 = 8;
+
+    /// `None` on machines without any adapter, like most CI runners, so the test can be skipped there
+    fn headless_state() -> Option<WgpuState>
+    {
+        match pollster::block_on(WgpuState::new_headless(Vec2::new(SIZE, SIZE), WgpuOptions::default()))
+        {
+            Ok(state) => Some(state),
+            Err(WgpuInitError::NoAdapter { .. }) => 
+            {
+                eprintln!("No adapter available, skipping the render target test");
+                None
+            },
+            Err(e) => panic!("Could not create a headless device: {}", e)
+        }
+    }
+
+    /// The image a target cleared to `color` should read back as, in the srgb target format
+    fn solid_image(color: Color) -> Vec<u8>
+    {
+        let encode = |v: f32| 
+        {
+            let srgb = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+            (srgb * 255.0).round() as u8
+        };
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+        let pixel = [encode(color.r), encode(color.g), encode(color.b), 255];
+        pixel.repeat((SIZE * SIZE) as usize)
     }
-}
\ No newline at end of file
+
+    fn assert_images_match(actual: &[u8], expected: &[u8])
+    {
+        assert_eq!(actual.len(), expected.len());
+        for (i, (a, e)) in actual.iter().zip(expected).enumerate()
+        {
+            // rounding of the srgb encode can differ between drivers
+            assert!(a.abs_diff(*e) <= 1, "Pixel {} channel {} is {}, expected {}", i / 4, i % 4, a, e);
+        }
+    }
+
+    /// Clears the target itself, like a stage that draws a background
+    struct ClearStage(Color);
+
+    impl RenderStage for ClearStage
+    {
+        fn target_ops(&self) -> TargetOps { TargetOps::clear(self.0) }
+
+        fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &Texture, ops: TargetOps)
+        {
+            apply_target_ops(device, queue, view, Some(depth_texture), ops);
+        }
+    }
+
+    #[test]
+    fn empty_target_reads_back_the_clear_color()
+    {
+        let Some(state) = headless_state() else { return; };
+        let target = RenderTarget::new(state.device(), state.surface_config());
+        let clear_color = Color::new(0.5, 0.25, 1.0, 1.0);
+
+        target.render(state.device(), state.queue(), clear_color, &mut []);
+        let pixels = target.read_rgba(state.device(), state.queue()).unwrap();
+        assert_images_match(&pixels, &solid_image(clear_color));
+    }
+
+    #[test]
+    fn first_stage_clear_replaces_the_clear_color()
+    {
+        let Some(state) = headless_state() else { return; };
+        let target = RenderTarget::new(state.device(), state.surface_config());
+        let mut stage = ClearStage(Color::RED);
+
+        target.render(state.device(), state.queue(), Color::BLUE, &mut [&mut stage]);
+        let pixels = target.read_rgba(state.device(), state.queue()).unwrap();
+        assert_images_match(&pixels, &solid_image(Color::RED));
+    }
+}
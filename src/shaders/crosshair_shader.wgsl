@@ -0,0 +1,36 @@
+// Draws a cross at the center of the screen, independent of the camera
+
+struct CrosshairInfo {
+    color: vec4<f32>,
+    // half the length of each arm and half of its thickness, in clip space
+    arm: vec2<f32>,
+    thickness: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> crosshair: CrosshairInfo;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // a quad for the horizontal arm then one for the vertical arm, wound counter clockwise
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+    );
+
+    var extent = vec2<f32>(crosshair.arm.x, crosshair.thickness.y);
+    if index >= 6u {
+        extent = vec2<f32>(crosshair.thickness.x, crosshair.arm.y);
+    }
+
+    return vec4<f32>(corners[index % 6u] * extent, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return crosshair.color;
+}
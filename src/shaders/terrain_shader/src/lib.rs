@@ -1,8 +1,9 @@
 #![no_std]
 
 use spirv_std::spirv;
-use spirv_std::glam::{Vec3, vec3, Vec4, vec4, Mat4, mat4, UVec3, uvec3, IVec3, IVec2, IVec4};
+use spirv_std::glam::{Vec2, Vec3, vec3, Vec4, vec4, Mat4, mat4, UVec2, UVec3, uvec3, IVec3, IVec2, IVec4};
 use spirv_std::arch::IndexUnchecked;
+use spirv_std::num_traits::Float;
 
 const SOUTH_FACE: [Vec3; 4] = [   
     vec3(0.0, 1.0, 1.0),
@@ -55,6 +56,32 @@ const VOXEL_FACE_ARRAY: [[Vec3; 4]; 6] = [
     WEST_FACE
 ];
 
+// in the same order as VOXEL_FACE_ARRAY
+const VOXEL_FACE_NORMALS: [Vec3; 6] = [
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 0.0, 1.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(-1.0, 0.0, 0.0),
+];
+
+#[repr(C)]
+pub struct LightInfo
+{
+    sun_direction: Vec3,
+    ambient: f32
+}
+
+#[repr(C)]
+pub struct FogInfo
+{
+    color: Vec4,
+    start: f32,
+    density: f32,
+    _padding: Vec2
+}
+
 #[spirv(vertex)]
 pub fn vs_main(
     // vertex
@@ -65,32 +92,64 @@ pub fn vs_main(
     voxel_position: UVec3,
     voxel_id: u32,
     face_index: u32,
+    face_size: UVec2,
     
     #[spirv(position)] clip_position: &mut Vec4,
 
     #[spirv(uniform, descriptor_set = 0, binding = 0)] view_proj: &Mat4,
     #[spirv(uniform, descriptor_set = 0, binding = 1)] voxel_size: &f32,
     #[spirv(uniform, descriptor_set = 0, binding = 2)] chunk_position: &IVec3,
-    #[spirv(uniform, descriptor_set = 0, binding = 3)] voxel_colors: &[Vec4; 4],
-
+    #[spirv(uniform, descriptor_set = 0, binding = 3)] voxel_colors: &[Vec4; 6],
+    #[spirv(uniform, descriptor_set = 0, binding = 5)] light: &LightInfo,
 
-    color_out: &mut Vec4
+    color_out: &mut Vec4,
+    view_depth: &mut f32
 ) 
 {
-    *color_out = unsafe { *voxel_colors.index_unchecked(voxel_id as usize) };
+    let color = unsafe { *voxel_colors.index_unchecked(voxel_id as usize) };
+    let normal = unsafe { *VOXEL_FACE_NORMALS.index_unchecked(face_index as usize) };
+    let diffuse = normal.dot(light.sun_direction).max(0.0);
+    let brightness = light.ambient + (1.0 - light.ambient) * diffuse;
+    *color_out = vec4(color.x * brightness, color.y * brightness, color.z * brightness, color.w);
     
     let mut vert_pos = unsafe 
     {
         *VOXEL_FACE_ARRAY.index_unchecked(face_index as usize).index_unchecked(index as usize)
     };
-    vert_pos += voxel_position.as_vec3() + chunk_position.as_vec3();
+    
+    // stretch the unit face over the voxels it covers, the axis along the normal is left alone
+    let size = face_size.as_vec2();
+    let scale = if face_index < 2 
+    {
+        vec3(size.x, 1.0, size.y)
+    }
+    else if face_index < 4 
+    {
+        vec3(size.x, size.y, 1.0)
+    }
+    else 
+    {
+        vec3(1.0, size.y, size.x)
+    };
+
+    vert_pos = vert_pos * scale + voxel_position.as_vec3() + chunk_position.as_vec3();
     vert_pos *= *voxel_size;
 
     *clip_position = *view_proj * vec4(vert_pos.x, vert_pos.y, vert_pos.z, 1.0);
+
+    // w is the view space depth for a perspective projection
+    *view_depth = clip_position.w;
 }
 
 #[spirv(fragment)]
-pub fn fs_main(color: Vec4, output: &mut Vec4)
+pub fn fs_main(
+    color: Vec4, 
+    view_depth: f32,
+    #[spirv(uniform, descriptor_set = 0, binding = 4)] fog: &FogInfo,
+    output: &mut Vec4
+)
 {
-    *output = color;
+    let fog_amount = 1.0 - (-fog.density * (view_depth - fog.start).max(0.0)).exp();
+    let fogged = color.lerp(fog.color, fog_amount);
+    *output = vec4(fogged.x, fogged.y, fogged.z, color.w);
 }
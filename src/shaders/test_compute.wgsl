@@ -7,15 +7,46 @@ var<uniform> chunk_size: vec3<u32>;
 @group(0) @binding(2)
 var<uniform> chunk_pos: vec3<i32>;
 
+struct NoiseArgs {
+    noise_type: u32,
+    octaves: u32,
+    lacunarity: f32,
+    persistence: f32,
+    seed: u32,
+}
+
+@group(0) @binding(3)
+var<uniform> noise_args: NoiseArgs;
+
+struct SurfaceArgs {
+    surface_level: f32,
+    dirt_depth: u32,
+}
+
+@group(0) @binding(4)
+var<uniform> surface_args: SurfaceArgs;
+
+// the number of solid voxels in the chunk, written by count_solid
+@group(0) @binding(5)
+var<storage, read_write> solid_count: atomic<u32>;
+
 const VOXEL_SIZE: f32 = 0.0625;
 const EPSILON: f32 = 0.00000001;
 const NOISE_HEIGHT_SCALE: f32 = 4.0;
-const NOISE_HEIGHT_OFFSET: f32 = 1.0;
 const NOISE_SCALE: f32 = 10.0;
 
+const SEED_OFFSET_RANGE: f32 = 1000.0;
+
 const WATER_HEIGHT: f32 = 2.0;
 const SAND_HEIGHT: f32 = 2.5;
 
+const VOXEL_AIR: i32 = -1;
+const VOXEL_WATER: i32 = 1;
+const VOXEL_SAND: i32 = 2;
+const VOXEL_GRASS: i32 = 3;
+const VOXEL_DIRT: i32 = 4;
+const VOXEL_STONE: i32 = 5;
+
 //  MIT License. © Ian McEwan, Stefan Gustavson, Munrocket, Johan Helsing
 fn mod289(x: vec2f) -> vec2f {
     return x - floor(x * (1. / 289.)) * 289.;
@@ -76,27 +107,144 @@ fn simplexNoise2(v: vec2f) -> f32 {
     return 130. * dot(m, g);
 }
 
+// Mirrored on the CPU by `pcg_hash` in world_gen.rs
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Mirrored on the CPU by `seed_offset` in world_gen.rs
+fn seed_offset(seed: u32) -> vec2f {
+    let a = pcg_hash(seed);
+    let b = pcg_hash(a);
+    return vec2(f32(a & 0xFFFFu), f32(b & 0xFFFFu)) / 65535.0 * SEED_OFFSET_RANGE;
+}
+
+fn hash2(p: vec2f) -> vec2f {
+    let q = vec2(dot(p, vec2(127.1, 311.7)), dot(p, vec2(269.5, 183.3)));
+    return fract(sin(q) * 43758.5453);
+}
+
+// Cellular noise, remapped from the distance to the closest feature point into [-1, 1]
+fn worley_noise(v: vec2f) -> f32 {
+    let cell = floor(v);
+    let local = fract(v);
+
+    var min_dist = 1.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2(f32(x), f32(y));
+            let point = hash2(cell + offset);
+            min_dist = min(min_dist, length(offset + point - local));
+        }
+    }
+
+    return min_dist * 2.0 - 1.0;
+}
+
+fn fbm_noise(v: vec2f) -> f32 {
+    var sum = 0.0;
+    var total_amplitude = 0.0;
+    var amplitude = 1.0;
+    var frequency = 1.0;
+
+    for (var i = 0u; i < max(noise_args.octaves, 1u); i++) {
+        sum += simplexNoise2(v * frequency) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= noise_args.persistence;
+        frequency *= noise_args.lacunarity;
+    }
+
+    return sum / total_amplitude;
+}
+
+fn ridged_noise(v: vec2f) -> f32 {
+    var sum = 0.0;
+    var total_amplitude = 0.0;
+    var amplitude = 1.0;
+    var frequency = 1.0;
+
+    for (var i = 0u; i < max(noise_args.octaves, 1u); i++) {
+        let ridge = 1.0 - abs(simplexNoise2(v * frequency));
+        sum += ridge * ridge * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= noise_args.persistence;
+        frequency *= noise_args.lacunarity;
+    }
+
+    return (sum / total_amplitude) * 2.0 - 1.0;
+}
+
+// Matches the `NoiseType` enum in world_gen.rs
+fn height_noise(v: vec2f) -> f32 {
+    let p = v + seed_offset(noise_args.seed);
+    switch noise_args.noise_type {
+        case 1u: { return ridged_noise(p); }
+        case 2u: { return fbm_noise(p); }
+        case 3u: { return worley_noise(p); }
+        default: { return simplexNoise2(p); }
+    }
+}
+
 fn sample_noise(x: u32, y: u32, z: u32) -> i32
 {
     let chunk_offset = vec3<f32>(f32(chunk_pos.x) * f32(chunk_size.x), f32(chunk_pos.y) * f32(chunk_size.y), f32(chunk_pos.z) * f32(chunk_size.z));
     let pos = vec2<f32>((f32(x) + chunk_offset.x + EPSILON) * VOXEL_SIZE, (f32(z) + chunk_offset.z + EPSILON) * VOXEL_SIZE);
-    let noise_height = simplexNoise2(pos / NOISE_SCALE) * NOISE_HEIGHT_SCALE + NOISE_HEIGHT_OFFSET;
+    let surface_height = height_noise(pos / NOISE_SCALE) * NOISE_HEIGHT_SCALE + surface_args.surface_level;
     let voxel_height = (f32(y) + chunk_offset.y) * VOXEL_SIZE;
 
-    var voxel = select(select(3, 2, voxel_height < SAND_HEIGHT), -1, voxel_height >= noise_height);
-    voxel = select(voxel, 1, voxel == -1 && voxel_height < WATER_HEIGHT);
-
-    return voxel;
+    if voxel_height >= surface_height
+    {
+        return select(VOXEL_AIR, VOXEL_WATER, voxel_height < WATER_HEIGHT);
+    }
+
+    // depth below the surface column, in voxels
+    let depth = (surface_height - voxel_height) / VOXEL_SIZE;
+    if depth < 1.0
+    {
+        return select(VOXEL_GRASS, VOXEL_SAND, voxel_height < SAND_HEIGHT);
+    }
+    else if depth < f32(surface_args.dirt_depth) + 1.0
+    {
+        return select(VOXEL_DIRT, VOXEL_SAND, voxel_height < SAND_HEIGHT);
+    }
+    
+    return VOXEL_STONE;
 }
 
+// x varies fastest and z slowest, the same as utils::flatten_index on the cpu
 fn index_of(x: u32, y: u32, z: u32) -> u32
 {
-    return (z * chunk_size.x * chunk_size.y) + (y * chunk_size.z) + x;
+    return (z * chunk_size.x * chunk_size.y) + (y * chunk_size.x) + x;
 }
 
-@compute @workgroup_size(1)
+// must match GENERATOR_WORKGROUP_SIZE in world_gen.rs
+@compute @workgroup_size(4, 4, 4)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) 
 {
+    // the last workgroup on each axis can hang past the edge of the chunk
+    if any(global_id >= chunk_size)
+    {
+        return;
+    }
+
     let index = index_of(global_id.x, global_id.y, global_id.z);
     v_indices[index] = sample_noise(global_id.x, global_id.y, global_id.z);
-}
\ No newline at end of file
+}
+
+// run after main on the same chunk, so the cpu can skip reading back chunks that are all air
+@compute @workgroup_size(4, 4, 4)
+fn count_solid(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if any(global_id >= chunk_size)
+    {
+        return;
+    }
+
+    let index = index_of(global_id.x, global_id.y, global_id.z);
+    if v_indices[index] > 0
+    {
+        atomicAdd(&solid_count, 1u);
+    }
+}
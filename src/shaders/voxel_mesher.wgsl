@@ -0,0 +1,93 @@
+// GPU mirror of `get_voxel_faces` and `has_face` in voxel.rs, for a chunk without neighbors
+
+@group(0) @binding(0)
+var<storage, read_write> voxel_ids: array<u32>; // laid out by utils::flatten_index, EMPTY_ID for empty voxels
+
+@group(0) @binding(1)
+var<uniform> chunk_size: vec3<u32>;
+
+// laid out like VoxelFace, all scalars so there is no padding between the fields
+struct VoxelFace {
+    x: u32,
+    y: u32,
+    z: u32,
+    voxel_id: u32,
+    direction: u32,
+    width: u32,
+    height: u32,
+}
+
+@group(0) @binding(2)
+var<storage, read_write> faces: array<VoxelFace>;
+
+@group(0) @binding(3)
+var<storage, read_write> face_count: atomic<u32>;
+
+const EMPTY_ID: u32 = 0xffffffffu;
+
+// same order as FaceDir::to_index
+const FACE_UP: u32 = 0u;
+const FACE_DOWN: u32 = 1u;
+const FACE_NORTH: u32 = 2u;
+const FACE_SOUTH: u32 = 3u;
+const FACE_EAST: u32 = 4u;
+const FACE_WEST: u32 = 5u;
+
+// the same as utils::flatten_index on the cpu
+fn index_of(position: vec3<i32>) -> u32
+{
+    let p = vec3<u32>(position);
+    return (p.z * chunk_size.x * chunk_size.y) + (p.y * chunk_size.x) + p.x;
+}
+
+fn face_offset(direction: u32) -> vec3<i32>
+{
+    switch direction
+    {
+        case 0u: { return vec3<i32>(0, 1, 0); }
+        case 1u: { return vec3<i32>(0, -1, 0); }
+        case 2u: { return vec3<i32>(0, 0, -1); }
+        case 3u: { return vec3<i32>(0, 0, 1); }
+        case 4u: { return vec3<i32>(1, 0, 0); }
+        default: { return vec3<i32>(-1, 0, 0); }
+    }
+}
+
+// faces on the chunk border are always visible, since there is no neighbor to hide them
+fn has_face(position: vec3<i32>, direction: u32) -> bool
+{
+    let adjacent = position + face_offset(direction);
+    let size = vec3<i32>(chunk_size);
+    if any(adjacent < vec3<i32>(0)) || any(adjacent >= size)
+    {
+        return true;
+    }
+
+    return voxel_ids[index_of(adjacent)] == EMPTY_ID;
+}
+
+// must match GENERATOR_WORKGROUP_SIZE in world_gen.rs
+@compute @workgroup_size(4, 4, 4)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if any(global_id >= chunk_size)
+    {
+        return;
+    }
+
+    let position = vec3<i32>(global_id);
+    let voxel_id = voxel_ids[index_of(position)];
+    if voxel_id == EMPTY_ID
+    {
+        return;
+    }
+
+    for (var direction = FACE_UP; direction <= FACE_WEST; direction++)
+    {
+        if has_face(position, direction)
+        {
+            let index = atomicAdd(&face_count, 1u);
+            faces[index] = VoxelFace(global_id.x, global_id.y, global_id.z, voxel_id, direction, 1u, 1u);
+        }
+    }
+}
@@ -8,12 +8,14 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(1) color: vec4<f32>,
+    @location(2) view_depth: f32,
 };
 
 struct InstanceInput {
     @location(2) position: vec3<u32>,
     @location(3) voxel_id: u32,
     @location(4) face_index: u32,
+    @location(5) face_size: vec2<u32>,
 };
 
 struct CameraUniform {
@@ -30,7 +32,34 @@ var<uniform> voxel_size: f32;
 var<uniform> chunk_position: vec3<i32>;
 
 @group(0) @binding(3)
-var<uniform> voxel_colors: array<vec4<f32>, 4>;
+var<uniform> voxel_colors: array<vec4<f32>, 6>;
+
+struct FogInfo {
+    color: vec4<f32>,
+    start: f32,
+    density: f32,
+}
+
+@group(0) @binding(4)
+var<uniform> fog: FogInfo;
+
+struct LightInfo {
+    sun_direction: vec3<f32>,
+    ambient: f32,
+}
+
+@group(0) @binding(5)
+var<uniform> light: LightInfo;
+
+// in the same order as voxel_face_array
+const voxel_face_normal_array = array<vec3<f32>, 6>(
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, -1.0, 0.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(-1.0, 0.0, 0.0),
+);
 
 const voxel_south_face_position_array = array<vec3<f32>, 4>(    
     vec3<f32>(0.0, 1.0, 1.0),
@@ -93,14 +122,31 @@ fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
     face_array.arr = voxel_face_array;
 
     var out: VertexOutput;
-    out.color = voxel_colors[instance.voxel_id];
+    var normal_array = voxel_face_normal_array;
+    let diffuse = max(dot(normal_array[instance.face_index], light.sun_direction), 0.0);
+    let brightness = light.ambient + (1.0 - light.ambient) * diffuse;
+    let color = voxel_colors[instance.voxel_id];
+    out.color = vec4<f32>(color.rgb * brightness, color.a);
 
     var vert_pos = face_array.arr[instance.face_index][vertex.index];
-    vert_pos += vec3<f32>(instance.position) + vec3<f32>(chunk_position);
+
+    // stretch the unit face over the voxels it covers, the axis along the normal is left alone
+    let size = vec2<f32>(instance.face_size);
+    var scale = vec3<f32>(1.0, size.y, size.x);
+    if instance.face_index < 2u {
+        scale = vec3<f32>(size.x, 1.0, size.y);
+    } else if instance.face_index < 4u {
+        scale = vec3<f32>(size.x, size.y, 1.0);
+    }
+
+    vert_pos = vert_pos * scale + vec3<f32>(instance.position) + vec3<f32>(chunk_position);
     vert_pos *= voxel_size;
 
     out.clip_position = camera.view_proj * vec4<f32>(vert_pos, 1.0);
 
+    // w is the view space depth for a perspective projection
+    out.view_depth = out.clip_position.w;
+
     return out;
 }
 
@@ -108,6 +154,7 @@ fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return in.color;
+    let fog_amount = 1.0 - exp(-fog.density * max(in.view_depth - fog.start, 0.0));
+    return vec4<f32>(mix(in.color.rgb, fog.color.rgb, fog_amount), in.color.a);
 }
  
\ No newline at end of file
@@ -4,6 +4,7 @@ use std::time::SystemTime;
 use crate::math::Vec3; 
 
 
+/// x varies fastest and z slowest, which every flattened voxel grid uses, including the buffers written by the compute shaders
 pub fn index_3d_to_index_1d(width: usize, height: usize, depth: usize, position: Vec3<usize>) -> usize
 {
     (position.z * width * height) + (position.y * width) + position.x
@@ -18,6 +19,23 @@ pub fn index_1d_to_index_3d(width: usize, height: usize, depth: usize, mut index
     Vec3::new(x, y, z)
 }
 
+/// `index_3d_to_index_1d` with the u32 sizes used on the gpu, this mirrors `index_of` in test_compute.wgsl and voxel_mesher.wgsl
+pub fn flatten_index(size: Vec3<u32>, position: Vec3<u32>) -> u32
+{
+    debug_assert!(position.x < size.x && position.y < size.y && position.z < size.z, "{:?} is outside of {:?}", position, size);
+    (position.z * size.x * size.y) + (position.y * size.x) + position.x
+}
+
+/// The inverse of `flatten_index`
+pub fn unflatten_index(size: Vec3<u32>, index: u32) -> Vec3<u32>
+{
+    debug_assert!(index < size.x * size.y * size.z, "{} is outside of {:?}", index, size);
+    let z = index / (size.x * size.y);
+    let y = (index / size.x) % size.y;
+    let x = index % size.x;
+    Vec3::new(x, y, z)
+}
+
 pub fn is_power_of_2(num: usize) -> bool 
 {
     (num != 0) && ((num & (num - 1)) == 0)
@@ -153,3 +171,46 @@ pub fn time_call<F, R>(f: F, name: &str) -> R
     r
 }
 
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // not a cube, so mixing up any of the strides shows
+    fn test_size() -> Vec3<u32>
+    {
+        Vec3::new(3, 5, 7)
+    }
+
+    #[test]
+    fn flatten_index_round_trips()
+    {
+        let size = test_size();
+        let mut index = 0;
+        for z in 0..size.z
+        {
+            for y in 0..size.y
+            {
+                for x in 0..size.x
+                {
+                    let position = Vec3::new(x, y, z);
+                    assert_eq!(flatten_index(size, position), index, "{:?}", position);
+                    assert_eq!(unflatten_index(size, index), position);
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flatten_index_matches_array_3d()
+    {
+        let size = test_size();
+        let array = Array3D::new(size.x as usize, size.y as usize, size.z as usize, |x, y, z| Vec3::new(x as u32, y as u32, z as u32));
+        for (index, position) in array.as_slice().iter().enumerate()
+        {
+            assert_eq!(flatten_index(size, *position), index as u32);
+        }
+    }
+}
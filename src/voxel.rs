@@ -4,15 +4,24 @@ pub mod world_gen;
 pub mod brick_map;
 pub mod terrain_renderer;
 pub mod voxel_rendering;
+pub mod voxel_file;
+pub mod obj_export;
+pub mod heightmap;
+pub mod gpu_mesher;
+pub mod coords;
 
-use crate::math::{Vec3, Color};
-use crate::utils::Array3D;
+use crate::math::{Vec2, Vec3, Color};
+use crate::utils::{Array3D, unflatten_index};
 
 use self::voxel_rendering::{VoxelMesh, FaceDir};
 
 pub trait VoxelStorage<T> : Sized where T : IVoxel
 {
     fn new(depth: usize) -> Self;
+
+    /// The smallest depth `new` accepts
+    fn min_depth() -> usize { 0 }
+
     fn depth(&self) -> usize;
     fn get(&self, index: Vec3<usize>) -> Option<T>;
     fn insert(&mut self, index: Vec3<usize>, value: Option<T>);
@@ -44,9 +53,79 @@ pub trait VoxelStorage<T> : Sized where T : IVoxel
         storage
     }
 
+    /// The inverse of `new_from_grid`, expands the storage into a dense grid
+    fn to_grid(&self) -> Array3D<Option<T>>
+    {
+        let length = self.length();
+        Array3D::new(length, length, length, |x, y, z| self.get([x, y, z].into()))
+    }
+
+    /// Flattens the storage into voxel ids laid out by `flatten_index`, using `empty_id` for empty voxels
+    fn to_ids(&self, empty_id: u32) -> Vec<u32>
+    {
+        let length = self.length() as u32;
+        let size = Vec3::new(length, length, length);
+        (0..(length * length * length))
+            .map(|index| self.get(unflatten_index(size, index).cast().unwrap()).map_or(empty_id, |v| v.id() as u32))
+            .collect()
+    }
+
     fn get_mesh(&self) -> VoxelMesh
     {
-        get_voxel_faces(self)
+        get_voxel_faces(self, &Neighbors::none())
+    }
+
+    /// Like `get_mesh`, but faces on the chunk border are culled against the neighboring chunks
+    fn get_mesh_with_neighbors(&self, neighbors: Neighbors<&Self>) -> VoxelMesh
+    {
+        get_voxel_faces(self, &neighbors)
+    }
+
+    /// Merges neighboring faces with the same voxel id into larger quads, which gives far fewer faces than `get_mesh`
+    fn get_greedy_mesh(&self) -> VoxelMesh
+    {
+        get_greedy_voxel_faces(self, &Neighbors::none())
+    }
+}
+
+/// The six chunks around a chunk, `None` where there is no chunk
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbors<T>
+{
+    pub up: Option<T>,
+    pub down: Option<T>,
+    pub north: Option<T>,
+    pub south: Option<T>,
+    pub east: Option<T>,
+    pub west: Option<T>
+}
+
+impl<T> Neighbors<T>
+{
+    pub fn none() -> Self
+    {
+        Self 
+        { 
+            up: None, 
+            down: None, 
+            north: None, 
+            south: None, 
+            east: None, 
+            west: None 
+        }
+    }
+
+    pub fn get(&self, face_dir: FaceDir) -> Option<&T>
+    {
+        match face_dir
+        {
+            FaceDir::Up => self.up.as_ref(),
+            FaceDir::Down => self.down.as_ref(),
+            FaceDir::North => self.north.as_ref(),
+            FaceDir::South => self.south.as_ref(),
+            FaceDir::East => self.east.as_ref(),
+            FaceDir::West => self.west.as_ref(),
+        }
     }
 }
 
@@ -78,15 +157,19 @@ impl<TStorage, TVoxel> VoxelStorageExt<TVoxel> for TStorage
 #[derive(Clone, Copy, Debug)]
 pub struct VoxelData
 {
+    name: &'static str,
     color: Color,
 }
 
 impl VoxelData
 {
-    pub fn new(color: Color) -> Self
+    pub fn new(name: &'static str, color: Color) -> Self
     {
-        Self { color }
+        Self { name, color }
     }
+
+    pub fn name(&self) -> &'static str { self.name }
+    pub fn color(&self) -> Color { self.color }
 }
 
 pub trait IVoxel : Clone + Eq
@@ -117,7 +200,7 @@ impl IVoxel for Voxel
     }
 }
 
-fn get_voxel_faces<TStorage, TVoxel>(data: &TStorage) -> VoxelMesh
+fn get_voxel_faces<TStorage, TVoxel>(data: &TStorage, neighbors: &Neighbors<&TStorage>) -> VoxelMesh
     where TStorage : VoxelStorage<TVoxel>, TVoxel : IVoxel
 {
     let mut faces = VoxelMesh::new();
@@ -129,7 +212,7 @@ fn get_voxel_faces<TStorage, TVoxel>(data: &TStorage) -> VoxelMesh
         {
             for z in 0..length 
             {
-                add_faces(data, Vec3::new(x, y, z), &mut faces);
+                add_faces(data, neighbors, Vec3::new(x, y, z), &mut faces);
             }
         }
     }
@@ -137,95 +220,121 @@ fn get_voxel_faces<TStorage, TVoxel>(data: &TStorage) -> VoxelMesh
     faces
 }
 
-fn has_face<TStorage, TVoxel>(data: &TStorage, index: Vec3<usize>, face_dir: FaceDir) -> bool
+fn get_greedy_voxel_faces<TStorage, TVoxel>(data: &TStorage, neighbors: &Neighbors<&TStorage>) -> VoxelMesh
     where TStorage : VoxelStorage<TVoxel>, TVoxel : IVoxel
 {
-    let size = data.length();
-    match face_dir
+    let mut mesh = VoxelMesh::new();
+    let length = data.length();
+
+    for face_dir in FaceDir::ALL
     {
-        FaceDir::South => 
-        {
-            if index.z > size
-            {
-                panic!("Index (x: {}, y: {}, z: {}) is not inside the chunk", index.x, index.y, index.z)
-            }
-            else if index.z == size - 1
-            {
-                true
-            }
-            else 
-            {
-                data.get([index.x, index.y, index.z + 1].into()).is_none()
-            }
-        },
-        FaceDir::North => 
-        {
-            if index.z == 0
-            {
-                true
-            }
-            else 
-            {
-                data.get([index.x, index.y, index.z - 1].into()).is_none()
-            }
-        },
-        FaceDir::West => 
-        {
-            if index.x == 0
-            {
-                true
-            }
-            else 
-            {
-                data.get([index.x - 1, index.y, index.z].into()).is_none()
-            }
-        },
-        FaceDir::East => 
+        // the axis along the face normal, then the width and height axes of the quad
+        let (n, u, v) = match face_dir
         {
-            if index.x > size
-            {
-                panic!("Index (x: {}, y: {}, z: {}) is not inside the chunk", index.x, index.y, index.z)
-            }
-            else if index.x == size - 1
-            {
-                true
-            }
-            else 
-            {
-                data.get([index.x + 1, index.y, index.z].into()).is_none()
-            }
-        },
-        FaceDir::Up => 
+            FaceDir::Up | FaceDir::Down => (1, 0, 2),
+            FaceDir::North | FaceDir::South => (2, 0, 1),
+            FaceDir::East | FaceDir::West => (0, 2, 1),
+        };
+
+        let mut mask: Vec<Option<u16>> = vec![None; length * length];
+        for slice in 0..length
         {
-            if index.y > size
-            {
-                panic!("Index (x: {}, y: {}, z: {}) is not inside the chunk", index.x, index.y, index.z)
-            }
-            else if index.y == size - 1
+            for j in 0..length
             {
-                true
+                for i in 0..length
+                {
+                    let mut index = [0; 3];
+                    index[n] = slice;
+                    index[u] = i;
+                    index[v] = j;
+                    let index = index.into();
+
+                    mask[i + j * length] = match data.get(index)
+                    {
+                        Some(voxel) if has_face(data, neighbors, index, face_dir) => Some(voxel.id()),
+                        _ => None
+                    };
+                }
             }
-            else 
+
+            for j in 0..length
             {
-                data.get([index.x, index.y + 1, index.z].into()).is_none()
+                let mut i = 0;
+                while i < length
+                {
+                    let Some(id) = mask[i + j * length] else { i += 1; continue; };
+
+                    let mut width = 1;
+                    while i + width < length && mask[i + width + j * length] == Some(id)
+                    {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    while j + height < length && (i..(i + width)).all(|k| mask[k + (j + height) * length] == Some(id))
+                    {
+                        height += 1;
+                    }
+
+                    for y in j..(j + height)
+                    {
+                        for x in i..(i + width)
+                        {
+                            mask[x + y * length] = None;
+                        }
+                    }
+
+                    let mut position = [0; 3];
+                    position[n] = slice as u32;
+                    position[u] = i as u32;
+                    position[v] = j as u32;
+                    mesh.add_quad(position.into(), face_dir, id, Vec2::new(width as u32, height as u32));
+
+                    i += width;
+                }
             }
-        },
-        FaceDir::Down => 
+        }
+    }
+
+    mesh
+}
+
+fn has_face<TStorage, TVoxel>(data: &TStorage, neighbors: &Neighbors<&TStorage>, index: Vec3<usize>, face_dir: FaceDir) -> bool
+    where TStorage : VoxelStorage<TVoxel>, TVoxel : IVoxel
+{
+    let size = data.length();
+    if index.x >= size || index.y >= size || index.z >= size
+    {
+        panic!("Index (x: {}, y: {}, z: {}) is not inside the chunk", index.x, index.y, index.z)
+    }
+
+    // the index of the adjacent voxel, and whether it is across the chunk border
+    let (adjacent, on_border) = match face_dir
+    {
+        FaceDir::South => ([index.x, index.y, (index.z + 1) % size], index.z == size - 1),
+        FaceDir::North => ([index.x, index.y, (index.z + size - 1) % size], index.z == 0),
+        FaceDir::East => ([(index.x + 1) % size, index.y, index.z], index.x == size - 1),
+        FaceDir::West => ([(index.x + size - 1) % size, index.y, index.z], index.x == 0),
+        FaceDir::Up => ([index.x, (index.y + 1) % size, index.z], index.y == size - 1),
+        FaceDir::Down => ([index.x, (index.y + size - 1) % size, index.z], index.y == 0),
+    };
+
+    if on_border
+    {
+        // without a neighbor there is nothing to hide the face
+        match neighbors.get(face_dir)
         {
-            if index.y == 0
-            {
-                true
-            }
-            else 
-            {
-                data.get([index.x, index.y - 1, index.z].into()).is_none()
-            }
-        },
-        _ => panic!("This should not be reached")
+            Some(neighbor) => neighbor.get(adjacent.into()).is_none(),
+            None => true
+        }
+    }
+    else 
+    {
+        data.get(adjacent.into()).is_none()
     }
 }
 
-fn add_faces<TStorage, TVoxel>(data: &TStorage, index: Vec3<usize>, mesh: &mut VoxelMesh)
+fn add_faces<TStorage, TVoxel>(data: &TStorage, neighbors: &Neighbors<&TStorage>, index: Vec3<usize>, mesh: &mut VoxelMesh)
     where TStorage : VoxelStorage<TVoxel>, TVoxel : IVoxel
 {
     let size = data.length();
@@ -238,33 +347,102 @@ fn add_faces<TStorage, TVoxel>(data: &TStorage, index: Vec3<usize>, mesh: &mut V
     let Some(voxel) = data.get([index.x, index.y, index.z].into()) else { return; };
     let pos = index.cast().unwrap();
 
-    if has_face(data, index, FaceDir::South)
+    if has_face(data, neighbors, index, FaceDir::South)
     {
         mesh.add_face(pos, FaceDir::South, voxel.id());
     }
 
-    if has_face(data, index, FaceDir::North)
+    if has_face(data, neighbors, index, FaceDir::North)
     {
         mesh.add_face(pos, FaceDir::North, voxel.id());
     }
 
-    if has_face(data, index, FaceDir::East)
+    if has_face(data, neighbors, index, FaceDir::East)
     {
         mesh.add_face(pos, FaceDir::East, voxel.id());
     }
 
-    if has_face(data, index, FaceDir::West)
+    if has_face(data, neighbors, index, FaceDir::West)
     {
         mesh.add_face(pos, FaceDir::West, voxel.id());
     }
 
-    if has_face(data, index, FaceDir::Up)
+    if has_face(data, neighbors, index, FaceDir::Up)
     {
         mesh.add_face(pos, FaceDir::Up, voxel.id());
     }
 
-    if has_face(data, index, FaceDir::Down)
+    if has_face(data, neighbors, index, FaceDir::Down)
     {
         mesh.add_face(pos, FaceDir::Down, voxel.id());
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::octree::Octree;
+
+    fn solid_chunk(depth: usize) -> Octree<Voxel>
+    {
+        let length = (2 as usize).pow(depth as u32);
+        let grid = Array3D::new(length, length, length, |_, _, _| Some(Voxel::new(0)));
+        Octree::new_from_grid(depth, &grid, |v| *v)
+    }
+
+    fn count_faces(mesh: &VoxelMesh, face_dir: FaceDir) -> usize
+    {
+        mesh.faces().iter().filter(|f| f.direction() == face_dir).count()
+    }
+
+    #[test]
+    fn shared_face_between_solid_chunks_is_culled()
+    {
+        let chunk = solid_chunk(1);
+        let east = solid_chunk(1);
+
+        let alone = chunk.get_mesh();
+        assert_eq!(count_faces(&alone, FaceDir::East), 4);
+        assert_eq!(alone.faces().len(), 24);
+
+        let neighbors = Neighbors { east: Some(&east), ..Neighbors::none() };
+        let culled = chunk.get_mesh_with_neighbors(neighbors);
+        assert_eq!(count_faces(&culled, FaceDir::East), 0);
+        assert_eq!(count_faces(&culled, FaceDir::West), 4);
+        assert_eq!(culled.faces().len(), 20);
+
+        // and the same from the other side
+        let neighbors = Neighbors { west: Some(&chunk), ..Neighbors::none() };
+        assert_eq!(count_faces(&east.get_mesh_with_neighbors(neighbors), FaceDir::West), 0);
+    }
+
+    // a plane merges into one quad per side, against a quad per voxel face
+    #[test]
+    fn greedy_mesh_merges_flat_plane()
+    {
+        let depth = 4;
+        let length = (2 as usize).pow(depth as u32);
+        let grid = Array3D::new(length, length, length, |_, y, _| if y == 0 { Some(Voxel::new(0)) } else { None });
+        let plane: Octree<Voxel> = Octree::new_from_grid(depth, &grid, |v| *v);
+
+        assert_eq!(plane.get_mesh().faces().len(), length * length * 2 + length * 4);
+        assert_eq!(plane.get_greedy_mesh().faces().len(), 6);
+    }
+
+    // the gpu mesher indexes these with index_of, which mirrors flatten_index
+    #[test]
+    fn to_ids_is_laid_out_by_flatten_index()
+    {
+        let mut storage: Octree<Voxel> = Octree::new(1);
+        storage.insert(Vec3::new(1, 0, 0), Some(Voxel::new(3)));
+        storage.insert(Vec3::new(0, 1, 1), Some(Voxel::new(5)));
+
+        let size = Vec3::new(2, 2, 2);
+        let ids = storage.to_ids(u32::MAX);
+        assert_eq!(ids.len(), 8);
+        assert_eq!(ids[crate::utils::flatten_index(size, Vec3::new(1, 0, 0)) as usize], 3);
+        assert_eq!(ids[crate::utils::flatten_index(size, Vec3::new(0, 1, 1)) as usize], 5);
+        assert_eq!(ids.iter().filter(|id| **id == u32::MAX).count(), 6);
+    }
+}
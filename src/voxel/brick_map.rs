@@ -334,6 +334,11 @@ impl<T, const D: usize> VoxelStorage<T> for SizedBrickMap<T, D> where T : IVoxel
         }
     }
 
+    fn min_depth() -> usize 
+    {
+        D + 1
+    }
+
     fn depth(&self) -> usize 
     {
         self.map.depth
@@ -0,0 +1,100 @@
+//! Conversions between the terrain's coordinate spaces. World positions are in world units,
+//! voxel indices count voxels from the world origin, chunk indices count chunks from the world origin,
+//! and local indices are a voxel's position inside its chunk.
+//! Negative coordinates round towards negative infinity, so the voxel just below the origin is -1 in chunk -1
+
+use cgmath::EuclideanSpace;
+
+use crate::math::{Vec3, Point3D, AABB};
+
+/// The voxel containing `world`
+pub fn world_to_voxel(world: Point3D<f32>, voxel_size: f32) -> Vec3<isize>
+{
+    world.to_vec().map(|v| (v / voxel_size).floor() as isize)
+}
+
+/// The world position of the voxel's minimum corner
+pub fn voxel_to_world(voxel_index: Vec3<isize>, voxel_size: f32) -> Point3D<f32>
+{
+    Point3D::from_vec(voxel_index.cast::<f32>().unwrap() * voxel_size)
+}
+
+/// The chunk the voxel is in, and its local index in that chunk
+pub fn voxel_to_chunk(voxel_index: Vec3<isize>, chunk_length: usize) -> (Vec3<isize>, Vec3<usize>)
+{
+    let length = chunk_length as isize;
+    let chunk_index = voxel_index.map(|v| v.div_euclid(length));
+    let local_index = voxel_index.map(|v| v.rem_euclid(length) as usize);
+    (chunk_index, local_index)
+}
+
+/// The inverse of `voxel_to_chunk`
+pub fn chunk_to_voxel(chunk_index: Vec3<isize>, local_index: Vec3<usize>, chunk_length: usize) -> Vec3<isize>
+{
+    chunk_to_voxel_origin(chunk_index, chunk_length) + local_index.cast::<isize>().unwrap()
+}
+
+/// The voxel index of the chunk's minimum corner
+pub fn chunk_to_voxel_origin(chunk_index: Vec3<isize>, chunk_length: usize) -> Vec3<isize>
+{
+    chunk_index * chunk_length as isize
+}
+
+/// The chunk containing `world`, and the local index of the voxel there
+pub fn world_to_chunk(world: Point3D<f32>, chunk_length: usize, voxel_size: f32) -> (Vec3<isize>, Vec3<usize>)
+{
+    voxel_to_chunk(world_to_voxel(world, voxel_size), chunk_length)
+}
+
+/// The world position of the chunk's minimum corner
+pub fn chunk_to_world_origin(chunk_index: Vec3<isize>, chunk_length: usize, voxel_size: f32) -> Point3D<f32>
+{
+    voxel_to_world(chunk_to_voxel_origin(chunk_index, chunk_length), voxel_size)
+}
+
+/// The world space box covering the whole chunk
+pub fn chunk_bounds(chunk_index: Vec3<isize>, chunk_length: usize, voxel_size: f32) -> AABB
+{
+    let min = chunk_to_world_origin(chunk_index, chunk_length, voxel_size);
+    AABB::new(min, min + Vec3::new(1.0, 1.0, 1.0) * (chunk_length as f32 * voxel_size))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const CHUNK_LENGTH: usize = 16;
+    const VOXEL_SIZE: f32 = 1.0 / 16.0;
+
+    #[test]
+    fn just_below_the_origin_is_the_last_voxel_of_chunk_minus_one()
+    {
+        let (chunk_index, local_index) = world_to_chunk(Point3D::new(-0.01, 0.0, 0.01), CHUNK_LENGTH, VOXEL_SIZE);
+        assert_eq!(chunk_index, Vec3::new(-1, 0, 0));
+        assert_eq!(local_index, Vec3::new(CHUNK_LENGTH - 1, 0, 0));
+    }
+
+    #[test]
+    fn voxel_to_chunk_round_trips_over_negative_indices()
+    {
+        for v in -40..40
+        {
+            let voxel_index = Vec3::new(v, -v, v * 3 - 7);
+            let (chunk_index, local_index) = voxel_to_chunk(voxel_index, CHUNK_LENGTH);
+
+            assert!(local_index.x < CHUNK_LENGTH && local_index.y < CHUNK_LENGTH && local_index.z < CHUNK_LENGTH);
+            assert_eq!(chunk_to_voxel(chunk_index, local_index, CHUNK_LENGTH), voxel_index);
+        }
+
+        assert_eq!(voxel_to_chunk(Vec3::new(-1, -16, -17), CHUNK_LENGTH), (Vec3::new(-1, -1, -2), Vec3::new(15, 0, 15)));
+    }
+
+    #[test]
+    fn chunk_bounds_cover_the_chunk()
+    {
+        let bounds = chunk_bounds(Vec3::new(-1, 0, 2), CHUNK_LENGTH, VOXEL_SIZE);
+        assert_eq!(bounds.min, Point3D::new(-1.0, 0.0, 2.0));
+        assert_eq!(bounds.max, Point3D::new(0.0, 1.0, 3.0));
+    }
+}
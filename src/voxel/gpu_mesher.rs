@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use wgpu::PipelineLayoutDescriptor;
+
+use crate::gpu_utils::{Storage, Uniform, MappedBuffer, BindGroup, Entry, GPUVec3, VertexBuffer};
+use crate::math::Vec3;
+
+use super::voxel_rendering::VoxelFace;
+use super::world_gen::workgroup_count;
+use super::{Voxel, VoxelStorage, VoxelStorageExt};
+
+/// Written in place of an id for empty voxels, since id 0 is a real voxel type
+const EMPTY_ID: u32 = u32::MAX;
+
+/// Builds chunk meshes in a compute shader instead of `VoxelStorage::get_mesh`. Every voxel face is its own quad,
+/// like the non-greedy cpu mesher, and faces on the chunk border are always kept since neighbors aren't taken into account
+pub struct VoxelMesher
+{
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+
+    chunk_length: u32,
+    voxel_buffer: Storage<u32>,
+    face_buffer: Storage<VoxelFace>,
+    face_count_buffer: Storage<u32>,
+    face_count_readback: MappedBuffer<u32>,
+    _chunk_size_uniform: Uniform<GPUVec3<u32>>,
+
+    bind_group: BindGroup,
+    compute_pipeline: wgpu::ComputePipeline
+}
+
+impl VoxelMesher
+{
+    /// The most faces a chunk can have. Along any line of voxels there are at most as many faces as voxels,
+    /// since faces come in pairs where the line goes in and out of solid voxels, so 3 per voxel over the 3 axes.
+    /// That only holds for an even length, which is why a single voxel chunk isn't supported
+    pub fn max_faces(chunk_depth: usize) -> u64
+    {
+        assert!(chunk_depth >= 1, "The gpu mesher needs a chunk depth of at least 1");
+        (2 as u64).pow(3 * chunk_depth as u32) * 3
+    }
+
+    /// Whether the face buffer for `chunk_depth` fits in one storage binding on `device`, which large chunks don't
+    pub fn is_supported(chunk_depth: usize, device: &wgpu::Device) -> bool
+    {
+        chunk_depth >= 1 && Self::max_faces(chunk_depth) * std::mem::size_of::<VoxelFace>() as u64 <= device.limits().max_storage_buffer_binding_size as u64
+    }
+
+    pub fn new(chunk_depth: usize, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self
+    {
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("../shaders/voxel_mesher.wgsl"));
+
+        let chunk_length = (2 as u32).pow(chunk_depth as u32);
+        let voxel_count = (chunk_length * chunk_length * chunk_length) as u64;
+        let max_faces = Self::max_faces(chunk_depth);
+
+        let voxel_buffer = Storage::<u32>::with_capacity(voxel_count, wgpu::ShaderStages::COMPUTE, &device);
+        let chunk_size_uniform = Uniform::new(GPUVec3::from(Vec3::new(chunk_length, chunk_length, chunk_length)), wgpu::ShaderStages::COMPUTE, &device);
+        let face_buffer = Storage::<VoxelFace>::with_capacity(max_faces, wgpu::ShaderStages::COMPUTE, &device);
+        let face_count_buffer = Storage::<u32>::with_capacity(1, wgpu::ShaderStages::COMPUTE, &device);
+        let face_count_readback = MappedBuffer::<u32>::with_capacity(1, wgpu::ShaderStages::COMPUTE, &device);
+
+        let entries: &[&dyn Entry] = &[
+            &voxel_buffer,
+            &chunk_size_uniform,
+            &face_buffer,
+            &face_count_buffer
+        ];
+
+        let bind_group = BindGroup::new(entries, &device);
+
+        let compute_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Voxel Mesher Pipeline Layout"),
+            bind_group_layouts: &[&bind_group.layout()],
+            push_constant_ranges: &[]
+        });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Voxel Mesher Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &cs_module,
+            entry_point: "main",
+        });
+
+        Self
+        {
+            device,
+            queue,
+            chunk_length,
+            voxel_buffer,
+            face_buffer,
+            face_count_buffer,
+            face_count_readback,
+            _chunk_size_uniform: chunk_size_uniform,
+            bind_group,
+            compute_pipeline
+        }
+    }
+
+    /// The faces come out in no particular order, but are the same set `get_mesh` gives.
+    /// Waits for the gpu to count the faces, the faces themselves never leave the gpu
+    pub fn mesh<S>(&mut self, storage: &S) -> VertexBuffer<VoxelFace>
+        where S : VoxelStorage<Voxel>
+    {
+        assert!(storage.length() as u32 == self.chunk_length, "Storage length {} doesn't match the mesher's chunk length {}", storage.length(), self.chunk_length);
+
+        self.voxel_buffer.enqueue_write(&storage.to_ids(EMPTY_ID), &self.queue);
+        self.face_count_buffer.enqueue_write(&[0], &self.queue);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Voxel Mesher Encoder") });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Voxel Mesher Pass"),
+            });
+
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group.bind_group(), &[]);
+            let groups = workgroup_count(Vec3::new(self.chunk_length, self.chunk_length, self.chunk_length));
+            compute_pass.dispatch_workgroups(groups.x, groups.y, groups.z);
+        }
+
+        self.face_count_buffer.copy_to_mapped(&mut self.face_count_readback, &mut encoder);
+        self.queue.submit(Some(encoder.finish()));
+
+        let face_count = self.face_count_readback.read(&self.device)[0] as u64;
+        if face_count == 0
+        {
+            return VertexBuffer::new(&[], &self.device, Some("Face Instance Buffer"));
+        }
+
+        // only the written faces are copied, the face buffer is reused for the next chunk
+        let mut face_instance_buffer = VertexBuffer::with_capacity(face_count, &self.device, Some("Face Instance Buffer"));
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Voxel Mesher Copy Encoder") });
+        self.face_buffer.buffer().copy_region(0, face_instance_buffer.mut_buffer(), 0, face_count, &mut encoder);
+        self.queue.submit(Some(encoder.finish()));
+
+        face_instance_buffer
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::gpu_utils::{WgpuState, WgpuOptions, WgpuInitError, GBuffer};
+    use crate::math::Vec2;
+    use crate::voxel::octree::Octree;
+
+    /// Position, direction and id, sorted since the gpu writes the faces in any order
+    fn face_set(faces: &[VoxelFace]) -> Vec<([u32; 3], u32, u16)>
+    {
+        let mut set = faces.iter()
+            .map(|f| (f.position().into(), f.direction().to_index(), f.voxel_id()))
+            .collect::<Vec<_>>();
+        set.sort();
+        set
+    }
+
+    #[test]
+    fn max_faces_bounds_a_checkerboard()
+    {
+        // every voxel of the checkerboard shows all 6 faces
+        assert_eq!(VoxelMesher::max_faces(1), 4 * 6);
+        assert_eq!(VoxelMesher::max_faces(2), 32 * 6);
+    }
+
+    #[test]
+    fn gpu_faces_match_cpu_faces()
+    {
+        let state = match pollster::block_on(WgpuState::new_headless(Vec2::new(1, 1), WgpuOptions::default()))
+        {
+            Ok(state) => state,
+            Err(WgpuInitError::NoAdapter { .. }) => 
+            {
+                eprintln!("No adapter available, skipping the gpu mesher test");
+                return;
+            },
+            Err(e) => panic!("Could not create a headless device: {}", e)
+        };
+
+        // a solid corner and some scattered voxels, touching the chunk border on every side
+        let depth = 2;
+        let grid = crate::utils::Array3D::new(4, 4, 4, |x, y, z| 
+        {
+            if x < 2 && y < 2 && z < 2 { Some(Voxel::new(1)) }
+            else if (x + 2 * y + 3 * z) % 5 == 0 { Some(Voxel::new(2)) }
+            else { None }
+        });
+        let storage: Octree<Voxel> = Octree::new_from_grid(depth, &grid, |v| *v);
+
+        let mut mesher = VoxelMesher::new(depth, state.device().clone(), state.queue().clone());
+        let faces = mesher.mesh(&storage);
+
+        let mut readback = GBuffer::<VoxelFace>::with_capacity(faces.length(), wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST, state.device(), None);
+        let mut encoder = state.device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Voxel Mesher Test Encoder") });
+        faces.buffer().copy(&mut readback, &mut encoder);
+        state.queue().submit(Some(encoder.finish()));
+
+        let cpu_faces = storage.get_mesh();
+        assert_eq!(face_set(&readback.read(state.device())), face_set(cpu_faces.faces()));
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::Path;
+
+use image::GrayImage;
+
+use crate::utils::Array3D;
+
+use super::{Voxel, VoxelStorage};
+
+/// Builds a storage with a column of voxels for every pixel, `height = pixel / 255 * max_height`.
+/// The top voxel of each column is `top_id` and the rest are `fill_id`, the image's y axis becomes the storage's z axis.
+/// The storage is the smallest cube that fits both the image and `max_height`
+pub fn from_heightmap<S>(image: &GrayImage, max_height: u32, top_id: u16, fill_id: u16) -> S
+    where S : VoxelStorage<Voxel>
+{
+    let size = image.width().max(image.height()).max(max_height).max(1) as usize;
+    let depth = size.next_power_of_two().trailing_zeros() as usize;
+    let length = 1 << depth;
+
+    let grid = Array3D::new(length, length, length, |x, y, z|
+    {
+        if x >= image.width() as usize || z >= image.height() as usize
+        {
+            return None;
+        }
+
+        let height = column_height(image.get_pixel(x as u32, z as u32).0[0], max_height) as usize;
+        if y + 1 == height { Some(top_id) }
+        else if y < height { Some(fill_id) }
+        else { None }
+    });
+
+    S::new_from_grid(depth, &grid, |id| id.map(Voxel::new))
+}
+
+pub fn load_heightmap<S>(path: &Path, max_height: u32, top_id: u16, fill_id: u16) -> Result<S, String>
+    where S : VoxelStorage<Voxel>
+{
+    let image = image::open(path).map_err(|e| e.to_string())?.to_luma8();
+    Ok(from_heightmap(&image, max_height, top_id, fill_id))
+}
+
+/// Rounds down, so only a fully white pixel reaches `max_height`
+pub fn column_height(pixel: u8, max_height: u32) -> u32
+{
+    pixel as u32 * max_height / 255
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::math::Vec3;
+    use crate::voxel::{IVoxel, VoxelStorageExt};
+    use crate::voxel::octree::Octree;
+
+    const TOP: u16 = 1;
+    const FILL: u16 = 2;
+
+    fn column(storage: &Octree<Voxel>, x: usize, z: usize) -> Vec<Option<u16>>
+    {
+        (0..storage.length()).map(|y| storage.get(Vec3::new(x, y, z)).map(|v| v.id())).collect()
+    }
+
+    #[test]
+    fn two_by_two_heightmap_builds_columns()
+    {
+        let image = GrayImage::from_raw(2, 2, vec![0, 255, 128, 255]).unwrap();
+        let storage: Octree<Voxel> = from_heightmap(&image, 2, TOP, FILL);
+
+        assert_eq!(storage.depth(), 1);
+        assert_eq!(column(&storage, 0, 0), vec![None, None]);
+        assert_eq!(column(&storage, 1, 0), vec![Some(FILL), Some(TOP)]);
+        assert_eq!(column(&storage, 0, 1), vec![Some(TOP), None]);
+        assert_eq!(column(&storage, 1, 1), vec![Some(FILL), Some(TOP)]);
+    }
+
+    #[test]
+    fn column_height_rounds_down()
+    {
+        assert_eq!(column_height(0, 16), 0);
+        assert_eq!(column_height(254, 16), 15);
+        assert_eq!(column_height(255, 16), 16);
+    }
+}
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{Write, BufWriter};
+use std::path::Path;
+
+use crate::math::Color;
+
+use super::{Voxel, VoxelStorage};
+use super::voxel_rendering::VoxelMesh;
+
+/// Writes the visible faces of `storage` as a Wavefront obj, with each voxel's color from `palette` as a vertex color.
+/// Positions are scaled by `voxel_size` and keep the game's y up axes, which is what Blender's importer expects.
+pub fn export_obj<S>(path: &Path, storage: &S, palette: &[Color], voxel_size: f32) -> std::io::Result<()>
+    where S : VoxelStorage<Voxel>
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_obj(&mut writer, &storage.get_greedy_mesh(), palette, voxel_size)?;
+    writer.flush()
+}
+
+/// Vertex colors are linear, voxel ids without a palette entry are written white
+pub fn write_obj<W>(writer: &mut W, mesh: &VoxelMesh, palette: &[Color], voxel_size: f32) -> std::io::Result<()>
+    where W : Write
+{
+    writeln!(writer, "# {} faces", mesh.faces().len())?;
+
+    for face in mesh.faces()
+    {
+        let color = palette.get(face.voxel_id() as usize).copied().unwrap_or(Color::WHITE);
+        for corner in face.corners()
+        {
+            let p = corner * voxel_size;
+            writeln!(writer, "v {} {} {} {} {} {}", p.x, p.y, p.z, color.r, color.g, color.b)?;
+        }
+    }
+
+    // the corners are in a zig zag, this goes around them counter clockwise like VOXEL_FACE_TRIANGLES
+    for i in 0..mesh.faces().len()
+    {
+        let first = i * 4 + 1;
+        writeln!(writer, "f {} {} {} {}", first + 2, first + 3, first + 1, first)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::math::Vec3;
+    use crate::voxel::octree::Octree;
+
+    #[test]
+    fn single_voxel_exports_a_quad_per_side()
+    {
+        let mut storage: Octree<Voxel> = Octree::new(1);
+        storage.insert(Vec3::new(1, 0, 1), Some(Voxel::new(0)));
+
+        let mut bytes = vec![];
+        write_obj(&mut bytes, &storage.get_greedy_mesh(), &[Color::RED], 0.5).unwrap();
+        let obj = String::from_utf8(bytes).unwrap();
+
+        let vertices = obj.lines().filter(|l| l.starts_with("v ")).collect::<Vec<_>>();
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 6);
+
+        // every corner is on the voxel scaled by the voxel size, colored from the palette
+        for line in vertices
+        {
+            let values = line[2..].split_whitespace().map(|v| v.parse::<f32>().unwrap()).collect::<Vec<_>>();
+            assert!([0.5, 1.0].contains(&values[0]) && [0.0, 0.5].contains(&values[1]) && [0.5, 1.0].contains(&values[2]), "{}", line);
+            assert_eq!(&values[3..], &[1.0, 0.0, 0.0]);
+        }
+    }
+}
@@ -98,6 +98,14 @@ impl<T> VoxelStorage<T> for Octree<T> where T : IVoxel + Copy + PartialEq
         }
     }
 
+    fn to_grid(&self) -> Array3D<Option<T>> 
+    {
+        let length = (2 as usize).pow(self.depth as u32);
+        let mut grid = Array3D::new_with_value(length, length, length, None);
+        fill_grid_from_node(&self.root, &mut grid);
+        grid
+    }
+
     // fn get_faces(&self, position: Vec3<isize>) -> Vec<VoxelFaceData> 
     // {
     //     let mut faces = vec![];
@@ -322,4 +330,35 @@ fn fill_node_from_grid<T, A, S>(node: &mut Node<T>, grid: &Array3D<A>, sampler:
 
         node.data = new_data;
     }
-}
\ No newline at end of file
+}
+
+/// Walks the tree once, filling the whole region of each leaf instead of looking up every voxel
+fn fill_grid_from_node<T>(node: &Node<T>, grid: &mut Array3D<Option<T>>)
+    where T : Copy + Clone + Eq
+{
+    match &node.data
+    {
+        NodeType::Empty => {},
+        NodeType::Leaf(leaf) => 
+        {
+            let (position, size) = node.bounds.get_bounds_location();
+            for x in position.x..(position.x + size)
+            {
+                for y in position.y..(position.y + size)
+                {
+                    for z in position.z..(position.z + size)
+                    {
+                        grid[(x, y, z)] = Some(*leaf);
+                    }
+                }
+            }
+        },
+        NodeType::Branches(branches) => 
+        {
+            for branch in branches.iter()
+            {
+                fill_grid_from_node(branch, grid);
+            }
+        }
+    }
+}
@@ -1,15 +1,21 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::ops::RangeBounds;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread::{JoinHandle, self};
 use std::time::SystemTime;
 
-use cgmath::Array;
+use cgmath::{Array, EuclideanSpace, InnerSpace, Zero};
 
-use crate::voxel::world_gen::VoxelGenerator;
+use crate::voxel::world_gen::{VoxelGenerator, NoiseArgs, SurfaceArgs};
 use super::terrain_renderer::ChunkRenderData;
-use super::{Voxel, VoxelData, VoxelStorage, VoxelStorageExt};
-use crate::math::Vec3;
+use super::gpu_mesher::VoxelMesher;
+use super::{Voxel, VoxelData, VoxelStorage, VoxelStorageExt, Neighbors, coords};
+use crate::math::{Vec3, Point3D};
+use crate::utils::Array3D;
+
+/// How many chunks `VoxelTerrain::tick` remeshes each frame, the rest wait for the next tick
+pub const DEFAULT_MAX_REMESHES_PER_TICK: usize = 4;
 
 pub struct Chunk<TStorage> where TStorage : VoxelStorage<Voxel>
 {
@@ -33,14 +39,35 @@ impl<TStorage> Chunk<TStorage> where TStorage : VoxelStorage<Voxel>
         }
     }
 
-    pub fn new(mut generator: MutexGuard<VoxelGenerator>, index: Vec3<isize>, voxels: Arc<Vec<VoxelData>>, chunk_depth: usize, device: &wgpu::Device) -> Self
+    pub fn new(mut generator: MutexGuard<VoxelGenerator>, index: Vec3<isize>, voxels: Arc<Vec<VoxelData>>, chunk_depth: usize) -> Self
+    {
+        let voxel_grid = generator.run_if_occupied(index.cast().unwrap());
+        drop(generator);
+
+        match voxel_grid
+        {
+            Some(voxel_grid) => Self::from_grid(index, &voxel_grid, voxels, chunk_depth),
+            None => Self::empty(index, voxels, chunk_depth)
+        }
+    }
+
+    /// An all air chunk, which has nothing to mesh
+    pub fn empty(index: Vec3<isize>, voxels: Arc<Vec<VoxelData>>, chunk_depth: usize) -> Self
+    {
+        Self 
+        {
+            data: TStorage::new(chunk_depth),
+            index,
+            voxels,
+            render_data: None
+        }
+    }
+
+    /// The chunk has no mesh until the terrain meshes it against its neighbors
+    pub fn from_grid(index: Vec3<isize>, voxel_grid: &Array3D<i32>, voxels: Arc<Vec<VoxelData>>, chunk_depth: usize) -> Self
     {
-        let length = (2 as isize).pow(chunk_depth as u32);
-        let chunk_position = index * length;
-        let voxel_grid = generator.run(index.cast().unwrap());
-        
         let now = SystemTime::now();
-        let data = TStorage::new_from_grid(chunk_depth, &voxel_grid, |i| {
+        let data = TStorage::new_from_grid(chunk_depth, voxel_grid, |i| {
             if *i > 0 
             {
                 Some(Voxel::new(*i as u16))
@@ -54,21 +81,12 @@ impl<TStorage> Chunk<TStorage> where TStorage : VoxelStorage<Voxel>
         let elapsed = now.elapsed().unwrap().as_micros() as f32 / 1000.0;
         println!("took {}ms to create and populate voxel storage", elapsed);
 
-        let render_data = if data.is_empty()
-        {
-            None
-        } 
-        else 
-        {
-            Some(ChunkRenderData::new(&data.get_mesh(), device))
-        };
-
         Self 
         {
             data,
             index,
             voxels,
-            render_data
+            render_data: None
         }
     }
 }
@@ -77,60 +95,68 @@ struct ChunkGenerator<TStorage> where TStorage : VoxelStorage<Voxel>
 {
     generator: Arc<Mutex<VoxelGenerator>>,
     queue: VecDeque<Vec3<isize>>,
-    thread: Option<JoinHandle<Chunk<TStorage>>>,
+    workers: Vec<JoinHandle<()>>,
+    max_workers: usize,
+    sender: Sender<Chunk<TStorage>>,
+    receiver: Receiver<Chunk<TStorage>>,
 
-    device: Arc<wgpu::Device>,
     chunk_depth: usize,
     voxels: Arc<Vec<VoxelData>>
 }
 
 impl<TStorage> ChunkGenerator<TStorage> where TStorage : VoxelStorage<Voxel> + Send + 'static
 {
-    fn new(generator: VoxelGenerator, chunk_depth: usize, voxels: Arc<Vec<VoxelData>>, device: Arc<wgpu::Device>) -> Self
+    fn new(generator: VoxelGenerator, chunk_depth: usize, voxels: Arc<Vec<VoxelData>>) -> Self
     {
+        let max_workers = thread::available_parallelism().map_or(1, |n| n.get());
+        let (sender, receiver) = mpsc::channel();
+
         Self 
         { 
             generator: Arc::new(Mutex::new(generator)),
             queue: VecDeque::new(),
-            thread: None,
-            device,
+            workers: vec![],
+            max_workers,
+            sender,
+            receiver,
             chunk_depth,
             voxels
         }
     }
 
-    fn tick(&mut self) -> Option<Chunk<TStorage>>
+    /// Returns every chunk that finished since the last tick, and starts workers for queued chunks
+    fn tick(&mut self) -> Vec<Chunk<TStorage>>
     {
-        let mut chunk = None;
-        if self.thread.is_some() && self.thread.as_ref().unwrap().is_finished()
-        {
-            let Some(thread) = std::mem::replace(&mut self.thread, None) else {
-                panic!("This should not have been called")
-            };
+        let chunks: Vec<_> = self.receiver.try_iter().collect();
 
-            chunk = Some(thread.join().unwrap());
+        // a worker that panicked never sends its chunk, so finished workers are joined here to surface that
+        let (finished, running): (Vec<_>, Vec<_>) = self.workers.drain(..).partition(|w| w.is_finished());
+        self.workers = running;
+        for worker in finished
+        {
+            if worker.join().is_err()
+            {
+                eprintln!("Warning: a chunk generation worker panicked");
+            }
         }
 
-        if self.thread.is_some() { return None; }
-
-        self.thread = None;
-
-        if let Some(front) = self.queue.pop_front()
+        while self.workers.len() < self.max_workers
         {
-            let device = self.device.clone();
+            let Some(chunk_index) = self.queue.pop_front() else { break; };
+
             let voxels = self.voxels.clone();
             let generator = self.generator.clone();
-            let chunk_index = front;
+            let sender = self.sender.clone();
             let chunk_depth = self.chunk_depth;
 
-            self.thread = Some(thread::spawn(move || {
+            self.workers.push(thread::spawn(move || {
                 let mutex = generator.lock().unwrap();
-                let chunk = Chunk::new(mutex, chunk_index, voxels, chunk_depth, &device);
-                chunk
-            }))
+                let chunk = Chunk::new(mutex, chunk_index, voxels, chunk_depth);
+                let _ = sender.send(chunk);
+            }));
         }
 
-        chunk
+        chunks
     }
 }
 
@@ -138,7 +164,13 @@ pub struct TerrainInfo
 {
     pub chunk_depth: usize,
     pub voxel_size: f32,
-    pub voxel_types: Arc<Vec<VoxelData>>
+    pub voxel_types: Arc<Vec<VoxelData>>,
+    pub noise_args: NoiseArgs,
+    pub surface_args: SurfaceArgs,
+    /// Meshes chunks with `VoxelMesher`, which doesn't cull faces against neighboring chunks.
+    /// The workers never mesh on the cpu, so with this set chunks are only meshed on the gpu.
+    /// Ignored when the device can't fit the mesher's buffers for the chunk depth
+    pub gpu_meshing: bool
 }
 
 impl TerrainInfo
@@ -149,40 +181,94 @@ impl TerrainInfo
     }
 }
 
+/// Where a ray hit the terrain, `voxel_index` is in voxels from the world origin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainHit
+{
+    pub voxel_index: Vec3<isize>,
+    /// The chunk the hit voxel is in
+    pub chunk_index: Vec3<isize>,
+    pub voxel: Voxel,
+    /// Where the ray entered the voxel, in world units
+    pub position: Point3D<f32>,
+    /// In world units
+    pub distance: f32,
+    /// The side of the voxel the ray came in through, zero if it started inside the voxel
+    pub normal: Vec3<isize>
+}
+
 pub struct VoxelTerrain<TStorage> where TStorage : VoxelStorage<Voxel>
 {
     info: TerrainInfo,
-    chunks: Vec<Chunk<TStorage>>,
+    chunks: HashMap<Vec3<isize>, Chunk<TStorage>>,
     device: Arc<wgpu::Device>,
-    generator: ChunkGenerator<TStorage>
+    generator: ChunkGenerator<TStorage>,
+    /// Chunks waiting on a mesh against their current neighbors
+    remesh_queue: VecDeque<Vec3<isize>>,
+    max_remeshes_per_tick: usize,
+    /// The center and view distance of the last `update_streaming`
+    streaming: Option<(Vec3<isize>, usize)>,
+    /// Only when `TerrainInfo::gpu_meshing` is set
+    mesher: Option<VoxelMesher>
 }
 
 impl<TStorage> VoxelTerrain<TStorage> where TStorage : VoxelStorage<Voxel> + Send + 'static
 {
     pub const fn chunk_size(&self) -> usize { (2 as usize).pow(self.info.chunk_depth as u32) }
     pub fn voxel_types(&self) -> &[VoxelData] { &self.info.voxel_types }
-    pub fn chunks(&self) -> &[Chunk<TStorage>] { &self.chunks }
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk<TStorage>> { self.chunks.values() }
+    pub fn chunk(&self, chunk_index: Vec3<isize>) -> Option<&Chunk<TStorage>> { self.chunks.get(&chunk_index) }
     pub fn info(&self) -> &TerrainInfo { &self.info }
+    pub fn max_workers(&self) -> usize { self.generator.max_workers }
+
+    /// The number of chunks that can be generated at once, defaults to the number of cpus
+    pub fn set_max_workers(&mut self, max_workers: usize)
+    {
+        self.generator.max_workers = max_workers.max(1);
+    }
+
+    pub fn max_remeshes_per_tick(&self) -> usize { self.max_remeshes_per_tick }
+
+    /// The number of chunks meshed each tick, defaults to `DEFAULT_MAX_REMESHES_PER_TICK`
+    pub fn set_max_remeshes_per_tick(&mut self, max_remeshes: usize)
+    {
+        self.max_remeshes_per_tick = max_remeshes.max(1);
+    }
 
     pub fn new(info: TerrainInfo, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self
     {
         let chunk_size = Vec3::from_value((2 as u32).pow(info.chunk_depth as u32));
 
-        let generator = VoxelGenerator::new(chunk_size, device.clone(), queue);
-        let voxel_types = info.voxel_types.clone();
         let chunk_depth = info.chunk_depth;
+        let mesher = match info.gpu_meshing
+        {
+            true if VoxelMesher::is_supported(chunk_depth, &device) => Some(VoxelMesher::new(chunk_depth, device.clone(), queue.clone())),
+            true => 
+            {
+                eprintln!("Warning: chunks of depth {} are too large for the gpu mesher, using the cpu mesher", chunk_depth);
+                None
+            },
+            false => None
+        };
+
+        let generator = VoxelGenerator::new(chunk_size, info.noise_args, info.surface_args, device.clone(), queue);
+        let voxel_types = info.voxel_types.clone();
         Self 
         { 
             info, 
-            chunks: vec![], 
-            device: device.clone(), 
-            generator: ChunkGenerator::new(generator, chunk_depth, voxel_types, device)
+            chunks: HashMap::new(), 
+            device, 
+            generator: ChunkGenerator::new(generator, chunk_depth, voxel_types),
+            remesh_queue: VecDeque::new(),
+            max_remeshes_per_tick: DEFAULT_MAX_REMESHES_PER_TICK,
+            streaming: None,
+            mesher
         }
     }
 
     pub fn generate_chunk(&mut self, chunk_index: Vec3<isize>) -> bool
     {
-        if self.chunks.iter().any(|c| c.index == chunk_index)
+        if self.chunks.contains_key(&chunk_index) || self.generator.queue.contains(&chunk_index)
         {
             false
         }
@@ -195,18 +281,126 @@ impl<TStorage> VoxelTerrain<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
 
     pub fn generate_chunk_immediate(&mut self, chunk_index: Vec3<isize>) -> bool
     {
-        if self.chunks.iter().any(|c| c.index == chunk_index)
+        if self.chunks.contains_key(&chunk_index)
         {
             false
         }
         else 
         {
-            let chunk: Chunk<TStorage> = Chunk::new(self.generator.generator.lock().unwrap(), chunk_index, self.info.voxel_types.clone(), self.info.chunk_depth, &self.device);
-            self.chunks.push(chunk);
+            let chunk: Chunk<TStorage> = Chunk::new(self.generator.generator.lock().unwrap(), chunk_index, self.info.voxel_types.clone(), self.info.chunk_depth);
+            self.chunks.insert(chunk_index, chunk);
+            self.remesh_with_neighbors(chunk_index);
             true
         }
     }
 
+    /// Generates every chunk in the inclusive range `min..=max` on the calling thread, batching the gpu work.
+    /// The chunks in the region are meshed against each other, so there are no faces between them.
+    pub fn generate_region_immediate(&mut self, min: Vec3<isize>, max: Vec3<isize>)
+    {
+        let region = self.generator.generator.lock().unwrap().run_region(min.cast().unwrap(), max.cast().unwrap());
+        let mut generated = vec![];
+        for (chunk_index, voxel_grid) in region
+        {
+            let chunk_index = chunk_index.cast().unwrap();
+            if self.chunks.contains_key(&chunk_index)
+            {
+                continue;
+            }
+
+            let chunk = Chunk::from_grid(chunk_index, &voxel_grid, self.info.voxel_types.clone(), self.info.chunk_depth);
+            self.chunks.insert(chunk_index, chunk);
+            generated.push(chunk_index);
+        }
+
+        for chunk_index in generated
+        {
+            self.remesh_chunk(chunk_index);
+        }
+    }
+
+    pub fn neighbors(&self, chunk_index: Vec3<isize>) -> Neighbors<&TStorage>
+    {
+        let find = |offset: Vec3<isize>| self.chunks.get(&(chunk_index + offset)).map(|c| &c.data);
+
+        Neighbors 
+        { 
+            up: find(Vec3::unit_y()), 
+            down: find(-Vec3::unit_y()), 
+            north: find(-Vec3::unit_z()), 
+            south: find(Vec3::unit_z()), 
+            east: find(Vec3::unit_x()), 
+            west: find(-Vec3::unit_x()) 
+        }
+    }
+
+    /// Rebuilds the mesh of a chunk against its current neighbors, returns false if the chunk doesn't exist.
+    /// This is a full remesh, so it is slow for large chunks. The gpu mesher, if used, ignores the neighbors
+    pub fn remesh_chunk(&mut self, chunk_index: Vec3<isize>) -> bool
+    {
+        let Some(chunk) = self.chunks.get(&chunk_index) else { return false; };
+
+        let data = &chunk.data;
+        let render_data = if data.is_empty()
+        {
+            None
+        }
+        else if let Some(mesher) = &mut self.mesher
+        {
+            Some(ChunkRenderData::from_face_buffer(mesher.mesh(data)))
+        }
+        else 
+        {
+            Some(ChunkRenderData::new(&data.get_mesh_with_neighbors(self.neighbors(chunk_index)), &self.device))
+        };
+
+        self.chunks.get_mut(&chunk_index).unwrap().render_data = render_data;
+        true
+    }
+
+    /// Remeshes a newly added chunk and the loaded chunks around it, so the faces between them are culled
+    fn remesh_with_neighbors(&mut self, chunk_index: Vec3<isize>)
+    {
+        self.remesh_chunk(chunk_index);
+        for neighbor in self.neighbors_to_remesh(chunk_index)
+        {
+            self.remesh_chunk(neighbor);
+        }
+    }
+
+    /// Like `remesh_with_neighbors`, but the meshing is spread over the next ticks
+    fn queue_remesh_with_neighbors(&mut self, chunk_index: Vec3<isize>)
+    {
+        self.queue_remesh(chunk_index);
+        for neighbor in self.neighbors_to_remesh(chunk_index)
+        {
+            self.queue_remesh(neighbor);
+        }
+    }
+
+    fn queue_remesh(&mut self, chunk_index: Vec3<isize>)
+    {
+        if !self.remesh_queue.contains(&chunk_index)
+        {
+            self.remesh_queue.push_back(chunk_index);
+        }
+    }
+
+    /// The loaded chunks around `chunk_index` whose border faces depend on it
+    fn neighbors_to_remesh(&self, chunk_index: Vec3<isize>) -> Vec<Vec3<isize>>
+    {
+        // the gpu mesher doesn't look at the neighbors, so their meshes wouldn't change
+        if self.mesher.is_some()
+        {
+            return vec![];
+        }
+
+        NEIGHBOR_OFFSETS.iter()
+            .map(|offset| chunk_index + *offset)
+            .filter(|neighbor| self.chunks.contains_key(neighbor))
+            .collect()
+    }
+
     pub fn generate_chunks<B>(&mut self, bounds: [B; 3]) where B : RangeBounds<isize> + IntoIterator<Item = isize> + Clone
     {
         for x in bounds[0].clone()
@@ -221,11 +415,263 @@ impl<TStorage> VoxelTerrain<TStorage> where TStorage : VoxelStorage<Voxel> + Sen
         }
     } 
 
+    /// Queues every chunk within `view_distance` chunks of `center` and unloads the chunks outside of it,
+    /// which also frees their gpu buffers. The loaded neighbors of unloaded chunks are remeshed, so their border faces show
+    pub fn update_streaming(&mut self, center: Vec3<isize>, view_distance: usize)
+    {
+        self.streaming = Some((center, view_distance));
+
+        for neighbor in unload_out_of_range(&mut self.chunks, center, view_distance)
+        {
+            self.queue_remesh(neighbor);
+        }
+        self.generator.queue.retain(|chunk_index| in_streaming_range(center, view_distance, *chunk_index));
+
+        let radius = view_distance as isize;
+        for x in -radius..=radius
+        {
+            for y in -radius..=radius
+            {
+                for z in -radius..=radius
+                {
+                    let chunk_index = center + Vec3::new(x, y, z);
+                    if in_streaming_range(center, view_distance, chunk_index)
+                    {
+                        self.generate_chunk(chunk_index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// `None` if the voxel is empty or its chunk isn't loaded. A chunk whose storage is smaller than `chunk_length`
+    /// reads as empty past its end, instead of panicking in the storage
+    pub fn voxel(&self, voxel_index: Vec3<isize>) -> Option<Voxel>
+    {
+        chunk_voxel(&self.chunks, self.info.chunk_length(), voxel_index)
+    }
+
+    /// Steps through the voxel grid along the ray until it hits a voxel or goes past `max_distance`, in world units.
+    /// Unloaded chunks count as empty.
+    pub fn raycast(&self, origin: Point3D<f32>, direction: Vec3<f32>, max_distance: f32) -> Option<TerrainHit>
+    {
+        raycast_chunks(&self.chunks, self.info.chunk_length(), self.info.voxel_size, origin, direction, max_distance)
+    }
+
     pub fn tick(&mut self)
     {
-        if let Some(chunk) = self.generator.tick()
+        for chunk in self.generator.tick()
         {
-            self.chunks.push(chunk);
+            // the chunk may have been generated some other way while the worker was busy,
+            // or left the streaming radius
+            let chunk_index = chunk.index;
+            let out_of_range = self.streaming.is_some_and(|(center, view_distance)| !in_streaming_range(center, view_distance, chunk_index));
+            if self.chunks.contains_key(&chunk_index) || out_of_range
+            {
+                continue;
+            }
+
+            self.chunks.insert(chunk_index, chunk);
+            self.queue_remesh_with_neighbors(chunk_index);
+        }
+
+        for _ in 0..self.max_remeshes_per_tick
+        {
+            let Some(chunk_index) = self.remesh_queue.pop_front() else { break; };
+            self.remesh_chunk(chunk_index);
         }
     }
-}
\ No newline at end of file
+}
+
+fn in_streaming_range(center: Vec3<isize>, view_distance: usize, chunk_index: Vec3<isize>) -> bool
+{
+    let radius = view_distance as isize;
+    let offset = chunk_index - center;
+    offset.x * offset.x + offset.y * offset.y + offset.z * offset.z <= radius * radius
+}
+
+/// Removes the chunks outside of the streaming range, and returns the loaded chunks that were next to one
+fn unload_out_of_range<TStorage>(chunks: &mut HashMap<Vec3<isize>, Chunk<TStorage>>, center: Vec3<isize>, view_distance: usize) -> Vec<Vec3<isize>>
+    where TStorage : VoxelStorage<Voxel>
+{
+    let mut unloaded = vec![];
+    chunks.retain(|chunk_index, _| 
+    {
+        let keep = in_streaming_range(center, view_distance, *chunk_index);
+        if !keep
+        {
+            unloaded.push(*chunk_index);
+        }
+        keep
+    });
+
+    let mut neighbors = vec![];
+    for chunk_index in unloaded
+    {
+        for offset in NEIGHBOR_OFFSETS
+        {
+            let neighbor = chunk_index + offset;
+            if chunks.contains_key(&neighbor) && !neighbors.contains(&neighbor)
+            {
+                neighbors.push(neighbor);
+            }
+        }
+    }
+
+    neighbors
+}
+
+const NEIGHBOR_OFFSETS: [Vec3<isize>; 6] = [
+    Vec3::new(1, 0, 0), Vec3::new(-1, 0, 0), 
+    Vec3::new(0, 1, 0), Vec3::new(0, -1, 0), 
+    Vec3::new(0, 0, 1), Vec3::new(0, 0, -1)
+];
+
+/// `VoxelTerrain::voxel` over just the chunks
+fn chunk_voxel<TStorage>(chunks: &HashMap<Vec3<isize>, Chunk<TStorage>>, chunk_length: usize, voxel_index: Vec3<isize>) -> Option<Voxel>
+    where TStorage : VoxelStorage<Voxel>
+{
+    let (chunk_index, local_index) = coords::voxel_to_chunk(voxel_index, chunk_length);
+
+    let data = &chunks.get(&chunk_index)?.data;
+    let data_length = data.length();
+    if local_index.x >= data_length || local_index.y >= data_length || local_index.z >= data_length
+    {
+        return None;
+    }
+
+    data.get(local_index)
+}
+
+/// `VoxelTerrain::raycast` over just the chunks, which doesn't need the gpu
+fn raycast_chunks<TStorage>(chunks: &HashMap<Vec3<isize>, Chunk<TStorage>>, chunk_length: usize, voxel_size: f32, origin: Point3D<f32>, direction: Vec3<f32>, max_distance: f32) -> Option<TerrainHit>
+    where TStorage : VoxelStorage<Voxel>
+{
+    if direction.magnitude2() == 0.0
+    {
+        return None;
+    }
+
+    let direction = direction.normalize();
+    let start = origin.to_vec() / voxel_size;
+    let max_distance = max_distance / voxel_size;
+
+    let mut voxel_index = start.map(|v| v.floor() as isize);
+    let step = direction.map(|d| if d > 0.0 { 1 } else if d < 0.0 { -1 } else { 0 });
+    let delta = direction.map(|d| if d == 0.0 { f32::INFINITY } else { (1.0 / d).abs() });
+
+    // how far along the ray the next voxel boundary is on each axis
+    let mut side = Vec3::zero();
+    for axis in 0..3
+    {
+        side[axis] = match step[axis]
+        {
+            1 => (start[axis].floor() + 1.0 - start[axis]) * delta[axis],
+            -1 => (start[axis] - start[axis].floor()) * delta[axis],
+            _ => f32::INFINITY
+        };
+    }
+
+    let mut distance = 0.0;
+    let mut normal = Vec3::zero();
+    loop 
+    {
+        if let Some(voxel) = chunk_voxel(chunks, chunk_length, voxel_index)
+        {
+            let (chunk_index, _) = coords::voxel_to_chunk(voxel_index, chunk_length);
+            let position = origin + direction * distance * voxel_size;
+            return Some(TerrainHit { voxel_index, chunk_index, voxel, position, distance: distance * voxel_size, normal });
+        }
+
+        let axis = if side.x < side.y && side.x < side.z { 0 } else if side.y < side.z { 1 } else { 2 };
+        distance = side[axis];
+        if distance > max_distance
+        {
+            return None;
+        }
+
+        side[axis] += delta[axis];
+        voxel_index[axis] += step[axis];
+        normal = Vec3::zero();
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::voxel::octree::Octree;
+
+    fn solid_storage(depth: usize) -> Octree<Voxel>
+    {
+        let length = (2 as usize).pow(depth as u32);
+        Octree::new_from_grid(depth, &Array3D::new(length, length, length, |_, _, _| Some(Voxel::new(1))), |v| *v)
+    }
+
+    fn chunk_map(chunks: Vec<(Vec3<isize>, Octree<Voxel>)>) -> HashMap<Vec3<isize>, Chunk<Octree<Voxel>>>
+    {
+        chunks.into_iter()
+            .map(|(index, data)| (index, Chunk { data, index, voxels: Arc::new(vec![]), render_data: None }))
+            .collect()
+    }
+
+    #[test]
+    fn generated_chunks_are_left_unmeshed()
+    {
+        // meshing is left to the terrain, so the gpu mesher isn't preceded by a cpu mesh
+        let grid = Array3D::new(4, 4, 4, |_, y, _| if y < 2 { 1 } else { 0 });
+        let chunk: Chunk<Octree<Voxel>> = Chunk::from_grid(Vec3::zero(), &grid, Arc::new(vec![]), 2);
+
+        assert!(!chunk.storage().is_empty());
+        assert!(chunk.render_data().is_none());
+    }
+
+    #[test]
+    fn chunks_leaving_the_radius_are_unloaded()
+    {
+        let chunks = (0..4).map(|x| (Vec3::new(x, 0, 0), Octree::new(1))).collect();
+        let mut chunks = chunk_map(chunks);
+
+        // moving the center to x = -1 with a view distance of 2 leaves x = 2 and 3 out of range
+        let neighbors = unload_out_of_range(&mut chunks, Vec3::new(-1, 0, 0), 2);
+
+        let mut loaded: Vec<_> = chunks.keys().map(|index| index.x).collect();
+        loaded.sort();
+        assert_eq!(loaded, vec![0, 1]);
+        assert_eq!(neighbors, vec![Vec3::new(1, 0, 0)]);
+    }
+
+    #[test]
+    fn storage_shorter_than_chunk_reads_as_empty()
+    {
+        // a chunk length of 4 with a storage of length 2
+        let chunks = chunk_map(vec![(Vec3::zero(), solid_storage(1))]);
+
+        assert_eq!(chunk_voxel(&chunks, 4, Vec3::new(1, 1, 1)), Some(Voxel::new(1)));
+        assert_eq!(chunk_voxel(&chunks, 4, Vec3::new(3, 0, 0)), None);
+
+        // runs along z = 3, past the end of the storage for the whole chunk
+        let hit = raycast_chunks(&chunks, 4, 1.0, Point3D::new(-0.5, 0.5, 3.5), Vec3::unit_x(), 10.0);
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn raycast_hits_the_far_chunk()
+    {
+        // the near chunk is empty, so the ray crosses into the solid chunk to the east
+        let chunks = chunk_map(vec![(Vec3::zero(), Octree::new(1)), (Vec3::unit_x(), solid_storage(1))]);
+        let voxel_size = 0.5;
+
+        let hit = raycast_chunks(&chunks, 2, voxel_size, Point3D::new(0.1, 0.3, 0.6), Vec3::unit_x(), 10.0).expect("The ray should hit the far chunk");
+
+        assert_eq!(hit.chunk_index, Vec3::new(1, 0, 0));
+        assert_eq!(hit.voxel_index, Vec3::new(2, 0, 1));
+        assert_eq!(hit.normal, Vec3::new(-1, 0, 0));
+        assert!((hit.position - Point3D::new(1.0, 0.3, 0.6)).magnitude() < 1e-5, "{:?}", hit.position);
+        assert!((hit.distance - 0.9).abs() < 1e-5);
+
+        // not far enough to reach it
+        assert_eq!(raycast_chunks(&chunks, 2, voxel_size, Point3D::new(0.1, 0.3, 0.6), Vec3::unit_x(), 0.8), None);
+    }
+}
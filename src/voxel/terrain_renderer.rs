@@ -1,13 +1,14 @@
 use std::{sync::Arc, cell::RefCell};
 
 use std::sync::{Mutex, MutexGuard};
+use std::{fs::File, io::{Write, Read}};
 
-use crate::rendering::{get_command_encoder, RenderPassInfo, build_render_pass};
-use crate::{math::{Vec3, Color}, rendering::{construct_render_pipeline, RenderPipelineInfo, RenderStage}, camera::{Camera, CameraUniform}};
-use crate::gpu_utils::{BindGroup, Uniform, VertexBuffer, VertexData, GPUVec3, IndexBuffer, GPUVec4};
+use crate::rendering::{get_command_encoder, RenderPassInfo, build_render_pass, DepthConfig, TargetOps, apply_target_ops};
+use crate::{math::Color, rendering::{construct_render_pipeline, RenderPipelineInfo, RenderStage}, camera::{Camera, CameraUniform}};
+use crate::gpu_utils::{BindGroup, Uniform, VertexBuffer, VertexData, GPUVec3, IndexBuffer, GPUVec4, GpuTimer};
 use crate::voxel::voxel_rendering::*;
 
-use super::{terrain::VoxelTerrain, VoxelStorage, Voxel};
+use super::{terrain::VoxelTerrain, VoxelStorage, Voxel, coords};
 
 pub struct ChunkRenderData
 {
@@ -25,6 +26,19 @@ impl ChunkRenderData
             face_instance_buffer: mesh.create_buffers(device)
         }
     }
+
+    /// For faces already on the gpu, e.g. from `VoxelMesher`
+    pub fn from_face_buffer(face_instance_buffer: VertexBuffer<VoxelFace>) -> Self
+    {
+        Self { face_instance_buffer }
+    }
+}
+
+/// Gpu timings from the last draw, `None` when profiling is off or the device can't do timestamp queries
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProfilingInfo
+{
+    pub draw_time_ms: Option<f32>
 }
 
 pub struct TerrainRenderStage<TStorage> where TStorage : VoxelStorage<Voxel>
@@ -34,7 +48,16 @@ pub struct TerrainRenderStage<TStorage> where TStorage : VoxelStorage<Voxel>
     camera: Camera,
     camera_uniform: RefCell<Uniform<CameraUniform>>,
     _voxel_size_uniform: Uniform<f32>,
-    _voxel_color_storage: Uniform<[Color; 4]>,
+    voxel_names: [&'static str; VOXEL_TYPE_COUNT],
+    voxel_colors: [Color; VOXEL_TYPE_COUNT],
+    voxel_color_uniform: Uniform<[Color; VOXEL_TYPE_COUNT]>,
+    voxel_colors_changed: bool,
+    fog: FogInfo,
+    fog_uniform: Uniform<FogInfo>,
+    fog_changed: bool,
+    light: LightInfo,
+    light_uniform: Uniform<LightInfo>,
+    light_changed: bool,
     chunk_position_uniform: RefCell<Uniform<GPUVec4<i32>>>,
 
     vertex_buffer: VertexBuffer<VoxelVertex>,
@@ -43,7 +66,14 @@ pub struct TerrainRenderStage<TStorage> where TStorage : VoxelStorage<Voxel>
     terrain: Arc<Mutex<VoxelTerrain<TStorage>>>,
     terrain_bind_group: BindGroup,
 
+    config: wgpu::SurfaceConfiguration,
+    polygon_mode: wgpu::PolygonMode,
+    sample_count: u32,
     render_pipeline: wgpu::RenderPipeline,
+
+    profiling: bool,
+    timer: Option<GpuTimer>,
+    profiling_info: ProfilingInfo
 }
 
 impl<TStorage> TerrainRenderStage<TStorage> where TStorage : VoxelStorage<Voxel> + Send + 'static
@@ -60,33 +90,35 @@ impl<TStorage> TerrainRenderStage<TStorage> where TStorage : VoxelStorage<Voxel>
 
         let chunk_position_uniform = Uniform::new(GPUVec4::new(0, 0, 0, 0), wgpu::ShaderStages::VERTEX, &device);
 
-        let voxel_colors: [Color; 4] = terrain_mutex
+        let voxel_colors: [Color; VOXEL_TYPE_COUNT] = terrain_mutex
             .info().voxel_types
             .iter()
-            .map(|v| v.color.into())
+            .map(|v| v.color())
+            .collect::<Vec<_>>().try_into().unwrap();
+
+        let voxel_names: [&'static str; VOXEL_TYPE_COUNT] = terrain_mutex
+            .info().voxel_types
+            .iter()
+            .map(|v| v.name())
             .collect::<Vec<_>>().try_into().unwrap();
 
         let voxel_color_storage = Uniform::new(voxel_colors, wgpu::ShaderStages::VERTEX, &device);
+        let fog_uniform = Uniform::new(FogInfo::NONE, wgpu::ShaderStages::FRAGMENT, &device);
+        let light_uniform = Uniform::new(LightInfo::NONE, wgpu::ShaderStages::VERTEX, &device);
 
         let vertex_buffer = VertexBuffer::new(&VOXEL_FACE_VERTICES, &device, Some("Voxel Vertex Buffer"));
         let index_buffer = IndexBuffer::new(&VOXEL_FACE_TRIANGLES, &device, Some("Voxel Index Buffer"));
 
-        let terrain_bind_group = BindGroup::new(&[&camera_uniform, &voxel_size_uniform, &chunk_position_uniform, &voxel_color_storage], &device);
+        let terrain_bind_group = BindGroup::new(&[&camera_uniform, &voxel_size_uniform, &chunk_position_uniform, &voxel_color_storage, &fog_uniform, &light_uniform], &device);
 
         println!("Camera uniform size {}", camera_uniform.size());
         println!("Voxel size uniform size {}", voxel_size_uniform.size());
         println!("Chunk position uniform size {}", chunk_position_uniform.size());
         println!("Voxel color uniform size {}", voxel_color_storage.size());
 
-        let shader = &device.create_shader_module(wgpu::include_spirv!(env!("terrain_shader.spv")));
-        let render_pipeline = construct_render_pipeline(&device, config, &RenderPipelineInfo {
-            shader,
-            vs_main: "vs_main",
-            fs_main: "fs_main",
-            vertex_buffers: &[&VoxelFace::desc(), &VoxelVertex::desc()],
-            bind_groups: &[terrain_bind_group.layout()],
-            label: Some("Voxel Render Pipeline")
-        });
+        let polygon_mode = wgpu::PolygonMode::Fill;
+        let sample_count = 1;
+        let render_pipeline = Self::create_render_pipeline(&device, config, &terrain_bind_group, polygon_mode, sample_count);
 
         drop(terrain_mutex);
 
@@ -96,13 +128,28 @@ impl<TStorage> TerrainRenderStage<TStorage> where TStorage : VoxelStorage<Voxel>
             camera, 
             camera_uniform: RefCell::new(camera_uniform), 
             _voxel_size_uniform: voxel_size_uniform, 
-            _voxel_color_storage: voxel_color_storage, 
+            voxel_names,
+            voxel_colors,
+            voxel_color_uniform: voxel_color_storage, 
+            voxel_colors_changed: false,
+            fog: FogInfo::NONE,
+            fog_uniform,
+            fog_changed: false,
+            light: LightInfo::NONE,
+            light_uniform,
+            light_changed: false,
             chunk_position_uniform: RefCell::new(chunk_position_uniform),
             vertex_buffer,
             index_buffer,
             terrain_bind_group, 
             terrain, 
-            render_pipeline 
+            config: config.clone(),
+            polygon_mode,
+            sample_count,
+            render_pipeline,
+            profiling: false,
+            timer: None,
+            profiling_info: ProfilingInfo::default()
         }
     }
 
@@ -110,26 +157,195 @@ impl<TStorage> TerrainRenderStage<TStorage> where TStorage : VoxelStorage<Voxel>
     {
         self.camera = camera;
     }
+
+    pub fn voxel_names(&self) -> &[&'static str; VOXEL_TYPE_COUNT] { &self.voxel_names }
+    pub fn voxel_colors(&self) -> &[Color; VOXEL_TYPE_COUNT] { &self.voxel_colors }
+
+    /// The new palette is uploaded on the next draw
+    pub fn set_voxel_colors(&mut self, colors: [Color; VOXEL_TYPE_COUNT])
+    {
+        if self.voxel_colors != colors
+        {
+            self.voxel_colors = colors;
+            self.voxel_colors_changed = true;
+        }
+    }
+
+    pub fn fog(&self) -> FogInfo { self.fog }
+
+    /// The new fog is uploaded on the next draw
+    pub fn set_fog(&mut self, fog: FogInfo)
+    {
+        if self.fog != fog
+        {
+            self.fog = fog;
+            self.fog_changed = true;
+        }
+    }
+
+    pub fn light(&self) -> LightInfo { self.light }
+
+    /// The new light is uploaded on the next draw
+    pub fn set_light(&mut self, light: LightInfo)
+    {
+        if self.light != light
+        {
+            self.light = light;
+            self.light_changed = true;
+        }
+    }
+
+    pub fn save_palette(&self, path: &str)
+    {
+        let yaml = serde_yaml::to_string(&self.voxel_colors).expect("Could not serialize voxel palette");
+
+        let mut file = File::create(path)
+            .expect(format!("Could not create file {}", path).as_str());
+
+        file.write_all(yaml.as_bytes())
+            .expect(format!("Could not write to file {}", path).as_str());
+    }
+
+    /// Keeps the current palette if there is no file at `path`, or it can't be read
+    pub fn load_palette(&mut self, path: &str)
+    {
+        let Ok(mut file) = File::open(path) else { return; };
+
+        let mut yaml = String::new();
+        if let Err(e) = file.read_to_string(&mut yaml)
+        {
+            eprintln!("Could not read file {}, keeping the default palette: {}", path, e);
+            return;
+        }
+
+        match serde_yaml::from_str::<[Color; VOXEL_TYPE_COUNT]>(&yaml)
+        {
+            Ok(colors) => self.set_voxel_colors(colors),
+            Err(e) => eprintln!("Could not deserialize voxel palette from {}, keeping the default palette: {}", path, e)
+        }
+    }
+
+    pub fn polygon_mode(&self) -> wgpu::PolygonMode
+    {
+        self.polygon_mode
+    }
+
+    /// Rebuilds the render pipeline, e.g. `PolygonMode::Line` for a wireframe view
+    pub fn set_polygon_mode(&mut self, polygon_mode: wgpu::PolygonMode)
+    {
+        if self.polygon_mode == polygon_mode
+        {
+            return;
+        }
+
+        self.polygon_mode = polygon_mode;
+        self.render_pipeline = Self::create_render_pipeline(&self.device, &self.config, &self.terrain_bind_group, polygon_mode, self.sample_count);
+    }
+
+    pub fn sample_count(&self) -> u32 { self.sample_count }
+
+    /// Rebuilds the render pipeline, has to match the sample count of the renderer's targets
+    pub fn set_sample_count(&mut self, sample_count: u32)
+    {
+        if self.sample_count == sample_count
+        {
+            return;
+        }
+
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::create_render_pipeline(&self.device, &self.config, &self.terrain_bind_group, self.polygon_mode, sample_count);
+    }
+
+    pub fn profiling_info(&self) -> ProfilingInfo { self.profiling_info }
+
+    /// Reading the timings back waits for the gpu to finish drawing the terrain, so this is off by default
+    pub fn set_profiling(&mut self, profiling: bool)
+    {
+        self.profiling = profiling;
+        if !profiling
+        {
+            self.profiling_info = ProfilingInfo::default();
+        }
+    }
+
+    fn create_render_pipeline(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, terrain_bind_group: &BindGroup, polygon_mode: wgpu::PolygonMode, sample_count: u32) -> wgpu::RenderPipeline
+    {
+        let shader = &device.create_shader_module(wgpu::include_spirv!(env!("terrain_shader.spv")));
+        construct_render_pipeline(device, config, &RenderPipelineInfo {
+            shader,
+            vs_main: "vs_main",
+            fs_main: "fs_main",
+            vertex_buffers: &[&VoxelFace::desc(), &VoxelVertex::desc()],
+            bind_groups: &[terrain_bind_group.layout()],
+            push_constant_ranges: &[],
+            blend: Some(wgpu::BlendState::REPLACE),
+            depth: Some(DepthConfig::DEFAULT),
+            polygon_mode,
+            sample_count,
+            label: Some("Voxel Render Pipeline")
+        })
+    }
 }
 
 impl<TStorage> RenderStage for TerrainRenderStage<TStorage> 
     where TStorage : VoxelStorage<Voxel> + Send + 'static
 {
-    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &crate::gpu_utils::Texture) 
+    fn on_draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView, depth_texture: &crate::gpu_utils::Texture, ops: TargetOps) 
     {
+        if self.voxel_colors_changed
+        {
+            self.voxel_color_uniform.enqueue_write(self.voxel_colors, queue);
+            self.voxel_colors_changed = false;
+        }
+
+        if self.fog_changed
+        {
+            self.fog_uniform.enqueue_write(self.fog, queue);
+            self.fog_changed = false;
+        }
+
+        if self.light_changed
+        {
+            self.light_uniform.enqueue_write(self.light, queue);
+            self.light_changed = false;
+        }
+
+        if self.profiling && self.timer.is_none()
+        {
+            self.timer = GpuTimer::new(device, queue);
+        }
+
+        let mut timer = if self.profiling { self.timer.as_mut() } else { None };
+        if let Some(timer) = &timer
+        {
+            let mut command_encoder = get_command_encoder(device);
+            timer.begin(&mut command_encoder);
+            queue.submit(std::iter::once(command_encoder.finish()));
+        }
+
         let terrain = self.terrain.lock().unwrap();
+        let frustum = self.camera.frustum();
+        let chunk_length = terrain.info().chunk_length();
+        let voxel_size = terrain.info().voxel_size;
+
+        // only the first chunk's pass clears
+        let mut ops = Some(ops);
         for chunk in terrain.chunks()
         {
             let Some(render_data) = chunk.render_data() else { continue; };
 
+            if !coords::chunk_bounds(chunk.index(), chunk_length, voxel_size).intersects_frustum(&frustum)
+            {
+                continue;
+            }
+
             // update camera view
             let mut data = CameraUniform::new();
             data.update_view_proj(&self.camera);
             self.camera_uniform.borrow_mut().enqueue_write(data, queue);
 
             // update chunk position
-            let chunk_index: Vec3<i32> = chunk.index().cast().unwrap();
-            let chunk_position = (chunk_index * terrain.info().chunk_length() as i32).extend(0);
+            let chunk_position = coords::chunk_to_voxel_origin(chunk.index(), chunk_length).cast::<i32>().unwrap().extend(0);
             self.chunk_position_uniform.borrow_mut().enqueue_write(chunk_position.into(), queue);
 
             let mut command_encoder = get_command_encoder(device);
@@ -143,6 +359,7 @@ impl<TStorage> RenderStage for TerrainRenderStage<TStorage>
                 vertex_buffers: &[render_data.face_instance_buffer().slice_all(), self.vertex_buffer.slice_all()],
                 index_buffer: Some(self.index_buffer.slice(..)),
                 index_format: wgpu::IndexFormat::Uint32,
+                ops: ops.take().unwrap_or(TargetOps::LOAD)
             };
 
             let mut render_pass = build_render_pass(info);
@@ -151,5 +368,19 @@ impl<TStorage> RenderStage for TerrainRenderStage<TStorage>
 
             queue.submit(std::iter::once(command_encoder.finish()));
         }
+
+        if let Some(ops) = ops
+        {
+            apply_target_ops(device, queue, view, Some(depth_texture), ops);
+        }
+
+        if let Some(timer) = &mut timer
+        {
+            let mut command_encoder = get_command_encoder(device);
+            timer.end(&mut command_encoder);
+            queue.submit(std::iter::once(command_encoder.finish()));
+
+            self.profiling_info.draw_time_ms = Some(timer.read(device));
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,289 @@
+use std::fs::File;
+use std::io::{Read, Write, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::math::Color;
+use crate::utils::Array3D;
+
+use super::{Voxel, VoxelStorage};
+
+/// The first bytes of every `.vxm` file
+pub const VXM_MAGIC: [u8; 4] = *b"VXM\0";
+pub const VXM_VERSION: u32 = 1;
+
+/// Written in place of an id for empty voxels, since id 0 is a real voxel type
+const EMPTY_ID: u32 = u32::MAX;
+
+/// The largest depth a file may claim, so a corrupt header can't make the loader allocate a huge grid
+const MAX_DEPTH: u32 = 10;
+
+#[derive(Debug)]
+pub enum VxmError
+{
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// The header or voxel runs don't describe a valid grid
+    Corrupt(String)
+}
+
+impl std::fmt::Display for VxmError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            VxmError::Io(e) => write!(f, "{}", e),
+            VxmError::BadMagic => write!(f, "Not a vxm file"),
+            VxmError::UnsupportedVersion(v) => write!(f, "Unsupported vxm version {}, expected {}", v, VXM_VERSION),
+            VxmError::Corrupt(reason) => write!(f, "Corrupt vxm file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VxmError {}
+
+impl From<std::io::Error> for VxmError
+{
+    fn from(value: std::io::Error) -> Self
+    {
+        VxmError::Io(value)
+    }
+}
+
+/// Saves `storage` and the colors of its voxel types. All numbers are little endian:
+/// magic, version, depth, run count, `(length, id)` runs over the voxels with x varying fastest,
+/// then the palette length and an rgba f32 color per entry
+pub fn save_voxels<S>(path: &Path, storage: &S, palette: &[Color]) -> Result<(), VxmError>
+    where S : VoxelStorage<Voxel>
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_voxels(&mut writer, storage, palette)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_voxels<S>(path: &Path) -> Result<(S, Vec<Color>), VxmError>
+    where S : VoxelStorage<Voxel>
+{
+    read_voxels(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn write_voxels<S, W>(writer: &mut W, storage: &S, palette: &[Color]) -> Result<(), VxmError>
+    where S : VoxelStorage<Voxel>, W : Write
+{
+    // most of a chunk is air, so runs keep the file small
+    let mut runs: Vec<(u32, u32)> = vec![];
+    for id in storage.to_ids(EMPTY_ID)
+    {
+        match runs.last_mut()
+        {
+            Some((length, run_id)) if *run_id == id => *length += 1,
+            _ => runs.push((1, id))
+        }
+    }
+
+    writer.write_all(&VXM_MAGIC)?;
+    write_u32(writer, VXM_VERSION)?;
+    write_u32(writer, storage.depth() as u32)?;
+
+    write_u32(writer, runs.len() as u32)?;
+    for (length, id) in runs
+    {
+        write_u32(writer, length)?;
+        write_u32(writer, id)?;
+    }
+
+    write_u32(writer, palette.len() as u32)?;
+    for color in palette
+    {
+        for channel in [color.r, color.g, color.b, color.a]
+        {
+            writer.write_all(&channel.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_voxels<S, R>(reader: &mut R) -> Result<(S, Vec<Color>), VxmError>
+    where S : VoxelStorage<Voxel>, R : Read
+{
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != VXM_MAGIC
+    {
+        return Err(VxmError::BadMagic);
+    }
+
+    let version = read_u32(reader)?;
+    if version != VXM_VERSION
+    {
+        return Err(VxmError::UnsupportedVersion(version));
+    }
+
+    let depth = read_u32(reader)?;
+    if depth > MAX_DEPTH
+    {
+        return Err(VxmError::Corrupt(format!("depth {} is larger than the max of {}", depth, MAX_DEPTH)));
+    }
+
+    if (depth as usize) < S::min_depth()
+    {
+        return Err(VxmError::Corrupt(format!("depth {} is smaller than the storage's min of {}", depth, S::min_depth())));
+    }
+
+    let length = 1usize << depth;
+    let voxel_count = length * length * length;
+
+    // not reserved up front, a truncated file shouldn't cost a full grid before failing
+    let run_count = read_u32(reader)?;
+    let mut ids = vec![];
+    for _ in 0..run_count
+    {
+        let run_length = read_u32(reader)? as usize;
+        let id = read_u32(reader)?;
+        if ids.len() + run_length > voxel_count
+        {
+            return Err(VxmError::Corrupt(format!("voxel runs are longer than the {} voxels in the grid", voxel_count)));
+        }
+
+        if id != EMPTY_ID && id > u16::MAX as u32
+        {
+            return Err(VxmError::Corrupt(format!("voxel id {} is out of range", id)));
+        }
+
+        ids.resize(ids.len() + run_length, id);
+    }
+
+    // checked before the grid is built, which expects exactly `voxel_count` ids
+    if ids.len() != voxel_count
+    {
+        return Err(VxmError::Corrupt(format!("voxel runs cover {} of the {} voxels in the grid", ids.len(), voxel_count)));
+    }
+
+    let palette_length = read_u32(reader)?;
+    let mut palette = vec![];
+    for _ in 0..palette_length
+    {
+        let mut channels = [0.0; 4];
+        for channel in channels.iter_mut()
+        {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            *channel = f32::from_le_bytes(bytes);
+        }
+
+        palette.push(Color::new(channels[0], channels[1], channels[2], channels[3]));
+    }
+
+    let grid = Array3D::from_vec(length, length, length, ids);
+    let storage = S::new_from_grid(depth as usize, &grid, |id| {
+        if *id == EMPTY_ID { None } else { Some(Voxel::new(*id as u16)) }
+    });
+
+    Ok((storage, palette))
+}
+
+fn write_u32<W>(writer: &mut W, value: u32) -> Result<(), VxmError> where W : Write
+{
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u32<R>(reader: &mut R) -> Result<u32, VxmError> where R : Read
+{
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::math::Vec3;
+    use crate::voxel::octree::Octree;
+    use crate::voxel::brick_map::SizedBrickMap;
+
+    fn write_to_bytes(storage: &Octree<Voxel>, palette: &[Color]) -> Vec<u8>
+    {
+        let mut bytes = vec![];
+        write_voxels(&mut bytes, storage, palette).unwrap();
+        bytes
+    }
+
+    fn header(depth: u32, run_count: u32) -> Vec<u8>
+    {
+        let mut bytes = VXM_MAGIC.to_vec();
+        for value in [VXM_VERSION, depth, run_count]
+        {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn round_trip_keeps_voxels_and_palette()
+    {
+        let mut storage: Octree<Voxel> = Octree::new(2);
+        storage.insert(Vec3::new(0, 0, 0), Some(Voxel::new(1)));
+        storage.insert(Vec3::new(3, 1, 2), Some(Voxel::new(0)));
+        storage.insert(Vec3::new(1, 3, 3), Some(Voxel::new(2)));
+        let palette = [Color::RED, Color::new(0.1, 0.2, 0.3, 0.4)];
+
+        let bytes = write_to_bytes(&storage, &palette);
+        let (loaded, loaded_palette): (Octree<Voxel>, _) = read_voxels(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.depth(), 2);
+        assert_eq!(loaded.to_ids(EMPTY_ID), storage.to_ids(EMPTY_ID));
+        assert_eq!(loaded_palette, palette);
+    }
+
+    #[test]
+    fn truncated_file_is_an_io_error()
+    {
+        let storage: Octree<Voxel> = Octree::new(1);
+        let bytes = write_to_bytes(&storage, &[Color::RED]);
+
+        for length in [2, 10, bytes.len() - 1]
+        {
+            let result = read_voxels::<Octree<Voxel>, _>(&mut &bytes[..length]);
+            assert!(matches!(result, Err(VxmError::Io(_))), "{} bytes gave {:?}", length, result.err());
+        }
+    }
+
+    #[test]
+    fn short_runs_are_corrupt()
+    {
+        // a depth 1 grid has 8 voxels, the one run only covers 7
+        let mut bytes = header(1, 1);
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(&EMPTY_ID.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        let result = read_voxels::<Octree<Voxel>, _>(&mut bytes.as_slice());
+        assert!(matches!(result, Err(VxmError::Corrupt(_))));
+    }
+
+    #[test]
+    fn bad_magic_is_rejected()
+    {
+        let mut bytes = write_to_bytes(&Octree::new(1), &[]);
+        bytes[0] = b'X';
+
+        let result = read_voxels::<Octree<Voxel>, _>(&mut bytes.as_slice());
+        assert!(matches!(result, Err(VxmError::BadMagic)));
+    }
+
+    #[test]
+    fn depth_below_storage_min_is_corrupt()
+    {
+        // a brick map needs a depth larger than its sub grids, loading this would underflow
+        let bytes = write_to_bytes(&Octree::new(2), &[]);
+
+        let result = read_voxels::<SizedBrickMap<Voxel, 4>, _>(&mut bytes.as_slice());
+        assert!(matches!(result, Err(VxmError::Corrupt(_))));
+    }
+}
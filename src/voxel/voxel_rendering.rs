@@ -1,6 +1,7 @@
-use crate::math::{Vec3, Color};
+use crate::math::{Vec2, Vec3, Color};
 use crate::gpu_utils::{Uniform, Storage, BindGroup, GPUVec3, Entry, VertexBuffer, VertexData};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FaceDir
 {
     Up,
@@ -25,8 +26,76 @@ impl FaceDir
             FaceDir::West =>    5,
         }
     }
+
+    pub fn from_index(index: u32) -> Option<FaceDir>
+    {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    pub const ALL: [FaceDir; 6] = [FaceDir::Up, FaceDir::Down, FaceDir::North, FaceDir::South, FaceDir::East, FaceDir::West];
+}
+
+/// Exponential distance fog applied to the terrain, `density` of 0 turns it off
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogInfo
+{
+    pub color: Color,
+    /// Distance from the camera before any fog is applied
+    pub start: f32,
+    pub density: f32,
+    _padding: [f32; 2]
+}
+
+unsafe impl bytemuck::Pod for FogInfo {}
+unsafe impl bytemuck::Zeroable for FogInfo {}
+
+impl FogInfo
+{
+    pub const NONE: FogInfo = FogInfo::new(Color::WHITE, 0.0, 0.0);
+
+    pub const fn new(color: Color, start: f32, density: f32) -> Self
+    {
+        Self 
+        { 
+            color, 
+            start, 
+            density, 
+            _padding: [0.0; 2] 
+        }
+    }
+}
+
+/// A directional sun on the terrain, `ambient` is the brightness of faces turned away from it.
+/// `NONE` lights every face fully, which is how the terrain looks without a sun
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightInfo
+{
+    /// Points towards the sun, its length is the sun's brightness so a zero vector turns the sun off
+    pub sun_direction: Vec3<f32>,
+    pub ambient: f32
+}
+
+unsafe impl bytemuck::Pod for LightInfo {}
+unsafe impl bytemuck::Zeroable for LightInfo {}
+
+impl LightInfo
+{
+    pub const NONE: LightInfo = LightInfo::new(Vec3::new(0.0, 1.0, 0.0), 1.0);
+
+    pub const fn new(sun_direction: Vec3<f32>, ambient: f32) -> Self
+    {
+        Self 
+        { 
+            sun_direction, 
+            ambient 
+        }
+    }
 }
 
+/// A quad on the side of one or more voxels. `size` is how many voxels it spans along the face's two axes, 
+/// which are x and z for up/down, x and y for north/south, and z and y for east/west
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct VoxelFace
@@ -34,6 +103,7 @@ pub struct VoxelFace
     position: Vec3<u32>,
     voxel_id: u32,
     direction: u32,
+    size: Vec2<u32>
 }
 
 unsafe impl bytemuck::Pod for VoxelFace {}
@@ -42,22 +112,59 @@ unsafe impl bytemuck::Zeroable for VoxelFace {}
 impl VoxelFace
 {
     pub fn new(position: Vec3<u32>, direction: FaceDir, voxel_id: u16) -> Self 
+    {
+        Self::with_size(position, direction, voxel_id, Vec2::new(1, 1))
+    }
+
+    pub fn with_size(position: Vec3<u32>, direction: FaceDir, voxel_id: u16, size: Vec2<u32>) -> Self 
     {
         Self 
         { 
             position,
             voxel_id: voxel_id as u32,
             direction: direction.to_index(), 
+            size
         }
     }
+
+    pub fn position(&self) -> Vec3<u32> { self.position }
+    pub fn voxel_id(&self) -> u16 { self.voxel_id as u16 }
+    pub fn direction(&self) -> FaceDir { FaceDir::from_index(self.direction).unwrap() }
+    pub fn size(&self) -> Vec2<u32> { self.size }
+
+    /// The corners in voxels, in the same order as `VOXEL_FACE_VERTICES`. This mirrors `vs_main` in the terrain shader
+    pub fn corners(&self) -> [Vec3<f32>; 4]
+    {
+        let corners: [[f32; 3]; 4] = match self.direction()
+        {
+            FaceDir::Up =>    [[0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 1.0], [1.0, 1.0, 1.0]],
+            FaceDir::Down =>  [[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]],
+            FaceDir::North => [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]],
+            FaceDir::South => [[0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [0.0, 0.0, 1.0], [1.0, 0.0, 1.0]],
+            FaceDir::East =>  [[1.0, 1.0, 1.0], [1.0, 1.0, 0.0], [1.0, 0.0, 1.0], [1.0, 0.0, 0.0]],
+            FaceDir::West =>  [[0.0, 1.0, 0.0], [0.0, 1.0, 1.0], [0.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        };
+
+        // stretch the unit face over the voxels it covers, the axis along the normal is left alone
+        let size = self.size.cast::<f32>().unwrap();
+        let scale = match self.direction()
+        {
+            FaceDir::Up | FaceDir::Down => Vec3::new(size.x, 1.0, size.y),
+            FaceDir::North | FaceDir::South => Vec3::new(size.x, size.y, 1.0),
+            FaceDir::East | FaceDir::West => Vec3::new(1.0, size.y, size.x),
+        };
+
+        let position = self.position.cast::<f32>().unwrap();
+        corners.map(|c| Vec3::new(c[0] * scale.x, c[1] * scale.y, c[2] * scale.z) + position)
+    }
 }
 
 impl VertexData for VoxelFace
 {
     fn desc() -> wgpu::VertexBufferLayout<'static> 
     {
-        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
-            wgpu::vertex_attr_array![2 => Uint32x3, 3 => Uint32, 4 => Uint32];
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+            wgpu::vertex_attr_array![2 => Uint32x3, 3 => Uint32, 4 => Uint32, 5 => Uint32x2];
 
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
@@ -101,6 +208,8 @@ impl VertexData for VoxelVertex
     }
 }
 
+pub const VOXEL_TYPE_COUNT: usize = 6;
+
 pub const VOXEL_FACE_VERTICES: [VoxelVertex; 4] = [VoxelVertex::new(0, Color::WHITE), VoxelVertex::new(1, Color::RED), VoxelVertex::new(2, Color::GREEN), VoxelVertex::new(3, Color::BLUE)];
 pub const VOXEL_FACE_TRIANGLES: [u32; 6] = [2, 1, 0, 2, 3, 1];
 
@@ -126,6 +235,11 @@ impl VoxelMesh
         self.faces.push(VoxelFace::new(location, direction, voxel_id))
     }
 
+    pub fn add_quad(&mut self, location: Vec3<u32>, direction: FaceDir, voxel_id: u16, size: Vec2<u32>)
+    {
+        self.faces.push(VoxelFace::with_size(location, direction, voxel_id, size))
+    }
+
     pub fn create_buffers(&self, device: &wgpu::Device) -> VertexBuffer<VoxelFace>
     {
         VertexBuffer::new(&self.faces, device, Some("Face Instance Buffer"))
@@ -1,42 +1,164 @@
 use std::sync::Arc;
 use wgpu::PipelineLayoutDescriptor;
-use crate::math::Vec3;
-use crate::gpu_utils::GPUVec3;
-use crate::gpu_utils::bind_group::{MappedBuffer, Storage, Uniform, BindGroup, Entry};
+use crate::math::{Vec2, Vec3};
+use crate::gpu_utils::{GPUVec3, GBuffer, StagingPool, MappedBuffer};
+use crate::gpu_utils::bind_group::{Storage, Uniform, BindGroup, Entry};
 use crate::utils::Array3D;
 
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseType
+{
+    Simplex = 0,
+    Ridged  = 1,
+    Fbm     = 2,
+    Worley  = 3,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseArgs
+{
+    pub noise_type: u32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+    pub seed: u32,
+}
+
+unsafe impl bytemuck::Pod for NoiseArgs {}
+unsafe impl bytemuck::Zeroable for NoiseArgs {}
+
+impl NoiseArgs
+{
+    pub const fn new(noise_type: NoiseType, octaves: u32, lacunarity: f32, persistence: f32, seed: u32) -> Self
+    {
+        Self 
+        { 
+            noise_type: noise_type as u32, 
+            octaves, 
+            lacunarity, 
+            persistence,
+            seed
+        }
+    }
+}
+
+impl Default for NoiseArgs
+{
+    /// Single octave simplex noise, which is what the generator has always used.
+    fn default() -> Self 
+    {
+        Self::new(NoiseType::Simplex, 1, 2.0, 0.5, 0)
+    }
+}
+
+pub const SEED_OFFSET_RANGE: f32 = 1000.0;
+
+/// CPU mirror of `pcg_hash` in test_compute.wgsl
+pub fn pcg_hash(input: u32) -> u32
+{
+    let state = input.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// CPU mirror of `seed_offset` in test_compute.wgsl, the offset applied to noise sample positions for a given seed
+pub fn seed_offset(seed: u32) -> Vec2<f32>
+{
+    let a = pcg_hash(seed);
+    let b = pcg_hash(a);
+    Vec2::new((a & 0xFFFF) as f32, (b & 0xFFFF) as f32) / 65535.0 * SEED_OFFSET_RANGE
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceArgs
+{
+    pub surface_level: f32,
+    pub dirt_depth: u32,
+}
+
+unsafe impl bytemuck::Pod for SurfaceArgs {}
+unsafe impl bytemuck::Zeroable for SurfaceArgs {}
+
+impl SurfaceArgs
+{
+    pub const fn new(surface_level: f32, dirt_depth: u32) -> Self
+    {
+        Self 
+        { 
+            surface_level, 
+            dirt_depth 
+        }
+    }
+}
+
+impl Default for SurfaceArgs
+{
+    fn default() -> Self 
+    {
+        Self::new(1.0, 4)
+    }
+}
+
+pub const REGION_BATCH_SIZE: usize = 4;
+
+/// The `@workgroup_size` of the generator and mesher compute shaders on each axis. 4x4x4 is 64 invocations,
+/// which fits in the 256 every device allows
+pub const GENERATOR_WORKGROUP_SIZE: u32 = 4;
+
+/// How many workgroups to dispatch on each axis to cover `size` voxels, rounding up.
+/// The shaders skip the invocations past the edge of the chunk
+pub fn workgroup_count(size: Vec3<u32>) -> Vec3<u32>
+{
+    size.map(|s| (s + GENERATOR_WORKGROUP_SIZE - 1) / GENERATOR_WORKGROUP_SIZE)
+}
+
 pub struct VoxelGenerator
 {
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
 
     chunk_size: Vec3<u32>,
-    staging_buffer: MappedBuffer<i32>,
+    staging_pool: StagingPool<i32>,
     storage_buffer: Storage<i32>,
     chunk_size_uniform: Uniform<GPUVec3<u32>>,
     chunk_position_uniform: Uniform<GPUVec3<i32>>,
+    noise_args_uniform: Uniform<NoiseArgs>,
+    surface_args_uniform: Uniform<SurfaceArgs>,
+    solid_count_buffer: Storage<u32>,
+    solid_count_readback: MappedBuffer<u32>,
 
     bind_group: BindGroup,
     compute_pipeline: wgpu::ComputePipeline,
+    count_pipeline: wgpu::ComputePipeline,
 }
 
 impl VoxelGenerator
 {
-    pub fn new(chunk_size: Vec3<u32>, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self 
+    pub fn new(chunk_size: Vec3<u32>, noise_args: NoiseArgs, surface_args: SurfaceArgs, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self 
     {
         let cs_module = device.create_shader_module(wgpu::include_wgsl!("../shaders/test_compute.wgsl"));
 
         let length = (chunk_size.x * chunk_size.y * chunk_size.z) as u64;
 
-        let staging_buffer = MappedBuffer::<i32>::with_capacity(length, wgpu::ShaderStages::COMPUTE, &device);
+        let staging_pool = StagingPool::new(wgpu::ShaderStages::COMPUTE, device.clone());
         let storage_buffer = Storage::<i32>::with_capacity(length, wgpu::ShaderStages::COMPUTE, &device);
         let chunk_size_uniform = Uniform::new(GPUVec3::from(chunk_size), wgpu::ShaderStages::COMPUTE, &device);
         let chunk_position_uniform = Uniform::<GPUVec3<i32>>::new_empty(wgpu::ShaderStages::COMPUTE, &device);
+        let noise_args_uniform = Uniform::new(noise_args, wgpu::ShaderStages::COMPUTE, &device);
+        let surface_args_uniform = Uniform::new(surface_args, wgpu::ShaderStages::COMPUTE, &device);
+        let solid_count_buffer = Storage::<u32>::with_capacity(1, wgpu::ShaderStages::COMPUTE, &device);
+        let solid_count_readback = MappedBuffer::<u32>::with_capacity(1, wgpu::ShaderStages::COMPUTE, &device);
 
         let entries: &[&dyn Entry] = &[
             &storage_buffer, 
             &chunk_size_uniform, 
-            &chunk_position_uniform
+            &chunk_position_uniform,
+            &noise_args_uniform,
+            &surface_args_uniform,
+            &solid_count_buffer
         ];
 
         let bind_group = BindGroup::new(entries, &device);
@@ -54,20 +176,42 @@ impl VoxelGenerator
             entry_point: "main",
         });
 
+        let count_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Solid Count Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &cs_module,
+            entry_point: "count_solid",
+        });
+
         Self 
         { 
             device, 
             queue, 
             chunk_size, 
-            staging_buffer, 
+            staging_pool, 
             storage_buffer, 
             chunk_position_uniform,
             chunk_size_uniform,
+            noise_args_uniform,
+            surface_args_uniform,
+            solid_count_buffer,
+            solid_count_readback,
             bind_group, 
             compute_pipeline, 
+            count_pipeline,
         }
     }
 
+    pub fn set_noise_args(&mut self, noise_args: NoiseArgs)
+    {
+        self.noise_args_uniform.enqueue_write(noise_args, &self.queue);
+    }
+
+    pub fn set_surface_args(&mut self, surface_args: SurfaceArgs)
+    {
+        self.surface_args_uniform.enqueue_write(surface_args, &self.queue);
+    }
+
     pub fn run(&mut self, chunk_pos: Vec3<i32>) -> Array3D<i32>
     {
         pollster::block_on(self.run_async(chunk_pos))
@@ -75,7 +219,18 @@ impl VoxelGenerator
 
     pub async fn run_async(&mut self, chunk_pos: Vec3<i32>) -> Array3D<i32>
     {
-        self.chunk_position_uniform.enqueue_write(chunk_pos.into(), &self.queue);
+        let (_, grid) = self.run_batch_async(&[chunk_pos]).await.pop().unwrap();
+        grid
+    }
+
+    /// Generates the chunk and counts its solid voxels on the gpu first, `None` if it is all air.
+    /// Only a single number is read back for empty chunks, instead of the whole grid
+    pub fn run_if_occupied(&mut self, chunk_pos: Vec3<i32>) -> Option<Array3D<i32>>
+    {
+        let length = (self.chunk_size.x * self.chunk_size.y * self.chunk_size.z) as u64;
+
+        self.chunk_position_uniform.enqueue_write(GPUVec3::from(chunk_pos), &self.queue);
+        self.solid_count_buffer.enqueue_write(&[0], &self.queue);
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
         {
@@ -83,17 +238,156 @@ impl VoxelGenerator
                 label: None,
             });
 
-            compute_pass.set_pipeline(&self.compute_pipeline);
             compute_pass.set_bind_group(0, &self.bind_group.bind_group(), &[]);
-            compute_pass.insert_debug_marker("compute random numbers");
-            compute_pass.dispatch_workgroups(self.chunk_size.x, self.chunk_size.y, self.chunk_size.z); // Number of cells to run, the (x,y,z) size of item being processed
+            let groups = workgroup_count(self.chunk_size);
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.dispatch_workgroups(groups.x, groups.y, groups.z);
+            compute_pass.set_pipeline(&self.count_pipeline);
+            compute_pass.dispatch_workgroups(groups.x, groups.y, groups.z);
         }
 
-        self.storage_buffer.copy_to_mapped(&mut self.staging_buffer, &mut encoder);
+        self.solid_count_buffer.copy_to_mapped(&mut self.solid_count_readback, &mut encoder);
+        self.queue.submit(Some(encoder.finish()));
 
+        if self.solid_count_readback.read(&self.device)[0] == 0
+        {
+            return None;
+        }
+
+        // the generated voxels are still in the storage buffer, so they don't need generating again
+        let mut staging_buffer = self.staging_pool.acquire(length);
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.storage_buffer.copy_to_mapped(&mut staging_buffer, &mut encoder);
         self.queue.submit(Some(encoder.finish()));
-        
-        let result = self.staging_buffer.read(&self.device);
-        Array3D::from_vec(self.chunk_size.x as usize, self.chunk_size.y as usize, self.chunk_size.z as usize, result)
+
+        let result = staging_buffer.read(&self.device);
+        Some(Array3D::from_vec(self.chunk_size.x as usize, self.chunk_size.y as usize, self.chunk_size.z as usize, result))
+    }
+
+    /// Generates every chunk in the inclusive range `min..=max`. Chunks are submitted to the gpu
+    /// in batches of up to `REGION_BATCH_SIZE`, with a single submit and readback per batch.
+    pub fn run_region(&mut self, min: Vec3<i32>, max: Vec3<i32>) -> Vec<(Vec3<i32>, Array3D<i32>)>
+    {
+        let mut positions = vec![];
+        for x in min.x..=max.x
+        {
+            for y in min.y..=max.y
+            {
+                for z in min.z..=max.z
+                {
+                    positions.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+
+        let mut chunks = Vec::with_capacity(positions.len());
+        for batch in positions.chunks(REGION_BATCH_SIZE)
+        {
+            chunks.extend(pollster::block_on(self.run_batch_async(batch)));
+        }
+
+        chunks
     }
-}
\ No newline at end of file
+
+    async fn run_batch_async(&mut self, chunk_positions: &[Vec3<i32>]) -> Vec<(Vec3<i32>, Array3D<i32>)>
+    {
+        assert!(chunk_positions.len() <= REGION_BATCH_SIZE, "Batch is larger than the max batch size");
+
+        let length = (self.chunk_size.x * self.chunk_size.y * self.chunk_size.z) as u64;
+        let mut staging_buffers = chunk_positions.iter().map(|_| self.staging_pool.acquire(length)).collect::<Vec<_>>();
+
+        // each chunk position is copied into the uniform inside the encoder, so that every dispatch sees its own position
+        let positions = chunk_positions.iter().map(|p| GPUVec3::from(*p)).collect::<Vec<_>>();
+        let position_buffer = GBuffer::new(&positions, wgpu::BufferUsages::COPY_SRC, &self.device, Some("Chunk Position Buffer"));
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for i in 0..chunk_positions.len()
+        {
+            self.chunk_position_uniform.copy_from(&position_buffer, i as u64, &mut encoder);
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                });
+
+                compute_pass.set_pipeline(&self.compute_pipeline);
+                compute_pass.set_bind_group(0, &self.bind_group.bind_group(), &[]);
+                compute_pass.insert_debug_marker("compute random numbers");
+                let groups = workgroup_count(self.chunk_size);
+                compute_pass.dispatch_workgroups(groups.x, groups.y, groups.z);
+            }
+
+            self.storage_buffer.copy_to_mapped(&mut staging_buffers[i], &mut encoder);
+        }
+
+        self.queue.submit(Some(encoder.finish()));
+
+        chunk_positions.iter().enumerate().map(|(i, chunk_pos)| {
+            let result = staging_buffers[i].read(&self.device);
+            (*chunk_pos, Array3D::from_vec(self.chunk_size.x as usize, self.chunk_size.y as usize, self.chunk_size.z as usize, result))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::gpu_utils::{WgpuState, WgpuOptions, WgpuInitError};
+
+    // reference values from the shader's pcg_hash, so the cpu mirror can't drift from it
+    #[test]
+    fn pcg_hash_matches_known_values()
+    {
+        assert_eq!(pcg_hash(0), 129708002);
+        assert_eq!(pcg_hash(1), 2831084092);
+        assert_eq!(pcg_hash(42), 1223963391);
+        assert_eq!(pcg_hash(u32::MAX), 3861530882);
+    }
+
+    #[test]
+    fn seed_offset_matches_known_values()
+    {
+        let offset = seed_offset(0);
+        assert!((offset.x - 12258.0 / 65535.0 * SEED_OFFSET_RANGE).abs() < 1e-3);
+        assert!((offset.y - 862.0 / 65535.0 * SEED_OFFSET_RANGE).abs() < 1e-3);
+
+        let offset = seed_offset(7);
+        assert!((offset.x - 4636.0 / 65535.0 * SEED_OFFSET_RANGE).abs() < 1e-3);
+        assert!((offset.y - 58013.0 / 65535.0 * SEED_OFFSET_RANGE).abs() < 1e-3);
+    }
+
+    #[test]
+    fn seed_offset_is_in_range()
+    {
+        for seed in 0..1000
+        {
+            let offset = seed_offset(seed);
+            assert!(offset.x >= 0.0 && offset.x <= SEED_OFFSET_RANGE);
+            assert!(offset.y >= 0.0 && offset.y <= SEED_OFFSET_RANGE);
+        }
+    }
+
+    #[test]
+    fn run_if_occupied_skips_sky_chunks()
+    {
+        let state = match pollster::block_on(WgpuState::new_headless(Vec2::new(1, 1), WgpuOptions::default()))
+        {
+            Ok(state) => state,
+            Err(WgpuInitError::NoAdapter { .. }) => 
+            {
+                eprintln!("No adapter available, skipping the generator test");
+                return;
+            },
+            Err(e) => panic!("Could not create a headless device: {}", e)
+        };
+
+        let mut generator = VoxelGenerator::new(Vec3::new(16, 16, 16), NoiseArgs::default(), SurfaceArgs::default(), state.device().clone(), state.queue().clone());
+
+        // a chunk is one world unit tall, and the surface stays within NOISE_HEIGHT_SCALE of surface_level
+        assert!(generator.run_if_occupied(Vec3::new(0, 10, 0)).is_none());
+
+        let grid = generator.run_if_occupied(Vec3::new(0, -10, 0)).expect("A chunk far below the surface should be solid");
+        assert!(grid.as_slice().iter().all(|id| *id > 0));
+    }
+}